@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use anyhow::bail;
+use pullover::Priority;
+use serde::Deserialize;
+
+use crate::config::AlertmanagerConfig;
+use crate::{input, template};
+
+/// Webhook payload of Alertmanager <https://prometheus.io/docs/alerting/latest/configuration/#webhook_config>
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+    version: String,
+    group_key: String,
+    status: String,
+    #[serde(default)]
+    receiver: String,
+    #[serde(default)]
+    group_labels: HashMap<String, String>,
+    #[serde(default)]
+    common_labels: HashMap<String, String>,
+    #[serde(default)]
+    common_annotations: HashMap<String, String>,
+    #[serde(default, rename = "externalURL")]
+    external_url: String,
+    #[serde(default)]
+    alerts: Vec<Alert>,
+}
+
+/// Alert in [`Payload`]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Alert {
+    status: String,
+    #[serde(default)]
+    labels: HashMap<String, String>,
+    #[serde(default)]
+    annotations: HashMap<String, String>,
+    #[serde(default, rename = "generatorURL")]
+    generator_url: String,
+}
+
+/// Notification translated from alert group
+#[derive(Debug)]
+pub struct Group {
+    /// Rendered title
+    pub title: String,
+    /// Rendered message
+    pub message: String,
+    /// First `generatorURL` of alerts
+    pub url: Option<String>,
+    /// Priority by severity, or of resolved alerts
+    pub priority: Priority,
+    /// Tag of emergency-priority notification, derived from group key
+    pub tag: String,
+    /// Whether all alerts of the group are resolved
+    pub resolved: bool,
+}
+
+/// Translates alert group into notification
+///
+/// Title and message are templates with `{status}`, `{receiver}`, `{groupKey}`, `{externalURL}`, `{count}`,
+/// `{alerts}` with one line per alert, and labels or annotations e.g. `{commonLabels.severity}`.
+pub fn translate(config: &AlertmanagerConfig, payload: &Payload) -> anyhow::Result<Group> {
+    if payload.version != "4" {
+        bail!("unsupported webhook version: {}", payload.version);
+    }
+    let resolved = payload.status == "resolved";
+
+    let alerts: Vec<String> = payload
+        .alerts
+        .iter()
+        .map(|a| {
+            let summary = a
+                .annotations
+                .get("summary")
+                .or_else(|| a.annotations.get("description"))
+                .or_else(|| a.labels.get("alertname"))
+                .map_or("", |s| s.as_str());
+            format!("[{}] {}", a.status, summary)
+        })
+        .collect();
+    let lookup = |name: &str| match name {
+        "status" => Some(payload.status.clone()),
+        "receiver" => Some(payload.receiver.clone()),
+        "groupKey" => Some(payload.group_key.clone()),
+        "externalURL" => Some(payload.external_url.clone()),
+        "count" => Some(payload.alerts.len().to_string()),
+        "alerts" => Some(alerts.join("\n")),
+        _ => {
+            let (map, key) = name.split_once('.')?;
+            match map {
+                "groupLabels" => payload.group_labels.get(key).cloned(),
+                "commonLabels" => payload.common_labels.get(key).cloned(),
+                "commonAnnotations" => payload.common_annotations.get(key).cloned(),
+                _ => None,
+            }
+        }
+    };
+
+    let severity = payload
+        .common_labels
+        .get(&config.severity_label)
+        .or_else(|| {
+            payload
+                .alerts
+                .iter()
+                .find_map(|a| a.labels.get(&config.severity_label))
+        });
+    let priority = match (resolved, config.resolved_priority) {
+        (true, Some(p)) => p.0,
+        (true, None) => Priority::Normal,
+        (false, _) => severity
+            .and_then(|s| config.priorities.get(s))
+            .map_or(Priority::Normal, |p| p.0),
+    };
+
+    let url = payload
+        .alerts
+        .iter()
+        .map(|a| &a.generator_url)
+        .find(|u| !u.is_empty())
+        .cloned();
+
+    Ok(Group {
+        title: input::fit_title(template::render(&config.title, &lookup)),
+        message: input::fit_message(template::render(&config.message, &lookup), true)?,
+        url,
+        priority,
+        tag: format!("am-{:016x}", fnv1a(&payload.group_key)),
+        resolved,
+    })
+}
+
+/// 64-bit FNV-1a, stable across Rust versions unlike [`std::collections::hash_map::DefaultHasher`]
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pullover::{Priority, TITLE_MAX_LENGTH};
+
+    use crate::alertmanager::{translate, Payload};
+    use crate::config::AlertmanagerConfig;
+
+    const PAYLOAD: &str = r#"{
+        "version": "4",
+        "groupKey": "{}:{alertname=\"DiskFull\"}",
+        "truncatedAlerts": 0,
+        "status": "firing",
+        "receiver": "pushover",
+        "groupLabels": {"alertname": "DiskFull"},
+        "commonLabels": {"alertname": "DiskFull", "severity": "critical"},
+        "commonAnnotations": {},
+        "externalURL": "http://alertmanager:9093",
+        "alerts": [
+            {
+                "status": "firing",
+                "labels": {"alertname": "DiskFull", "severity": "critical", "instance": "db1"},
+                "annotations": {"summary": "disk of db1 is full"},
+                "startsAt": "2021-08-20T12:00:00Z",
+                "endsAt": "0001-01-01T00:00:00Z",
+                "generatorURL": "http://prometheus:9090/graph?g0.expr=disk",
+                "fingerprint": "0123456789abcdef"
+            },
+            {
+                "status": "firing",
+                "labels": {"alertname": "DiskFull", "severity": "critical", "instance": "db2"},
+                "annotations": {},
+                "startsAt": "2021-08-20T12:00:00Z",
+                "endsAt": "0001-01-01T00:00:00Z",
+                "generatorURL": "http://prometheus:9090/graph?g0.expr=disk",
+                "fingerprint": "fedcba9876543210"
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_translate() {
+        let config = AlertmanagerConfig::default();
+        let payload: Payload = serde_json::from_str(PAYLOAD).unwrap();
+        let group = translate(&config, &payload).unwrap();
+        assert_eq!("[firing] DiskFull", group.title);
+        assert_eq!(
+            "[firing] disk of db1 is full\n[firing] DiskFull",
+            group.message
+        );
+        assert_eq!(
+            Some("http://prometheus:9090/graph?g0.expr=disk"),
+            group.url.as_deref()
+        );
+        assert_eq!(Priority::High, group.priority);
+        assert!(!group.resolved);
+
+        let resolved = PAYLOAD.replace(r#""status": "firing""#, r#""status": "resolved""#);
+        let payload: Payload = serde_json::from_str(&resolved).unwrap();
+        let resolved = translate(&config, &payload).unwrap();
+        assert_eq!(Priority::Normal, resolved.priority);
+        assert!(resolved.resolved);
+        assert_eq!(group.tag, resolved.tag);
+
+        let payload: Payload =
+            serde_json::from_str(&PAYLOAD.replace(r#""version": "4""#, r#""version": "3""#))
+                .unwrap();
+        assert!(translate(&config, &payload).is_err());
+
+        let long = PAYLOAD.replace(
+            r#""commonLabels": {"alertname": "DiskFull""#,
+            &format!(r#""commonLabels": {{"alertname": "{}""#, "x".repeat(300)),
+        );
+        let payload: Payload = serde_json::from_str(&long).unwrap();
+        let group = translate(&config, &payload).unwrap();
+        assert_eq!(TITLE_MAX_LENGTH, group.title.chars().count());
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::Context;
+use pullover::Priority;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+
+use crate::receive::Hook;
+use crate::webhook::Route;
+
+/// Configuration file of po2 in TOML
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Shell command printing API token, when neither `--token` nor `--token-file` is given
+    pub token_cmd: Option<String>,
+    /// Shell command printing user key, when neither `--user` nor `--user-file` is given
+    pub user_cmd: Option<String>,
+    /// Options of `po2 serve`
+    pub serve: ServeConfig,
+    /// Options of Alertmanager webhook receiver of `po2 serve`
+    pub alertmanager: AlertmanagerConfig,
+    /// Options of `po2 receive`
+    pub receive: ReceiveConfig,
+}
+
+/// Options of `po2 serve`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ServeConfig {
+    /// Clients allowed to send notifications through the relay, anyone can when empty
+    pub clients: Vec<Client>,
+    /// Routes translating JSON webhooks into notifications
+    pub routes: Vec<Route>,
+}
+
+/// Options of `po2 receive`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ReceiveConfig {
+    /// Commands run for received messages
+    pub hooks: Vec<Hook>,
+}
+
+/// Client of the relay authenticated by bearer token
+#[derive(Debug, Deserialize)]
+pub struct Client {
+    /// Name shown in request log
+    pub name: String,
+    /// Bearer token sent in `Authorization` header
+    pub token: String,
+}
+
+/// Options of Alertmanager webhook receiver of `po2 serve`
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AlertmanagerConfig {
+    /// Template of title
+    pub title: String,
+    /// Template of message
+    pub message: String,
+    /// User or group key receiving alerts, otherwise the one of po2
+    pub user: Option<String>,
+    /// Label of alert to look up priority in `priorities`
+    pub severity_label: String,
+    /// Priority by severity, normal priority when severity is not found
+    pub priorities: HashMap<String, PriorityValue>,
+    /// Priority of notification when alerts are resolved
+    pub resolved_priority: Option<PriorityValue>,
+    /// Seconds between retries of emergency-priority notification
+    pub retry: u32,
+    /// Seconds before emergency-priority notification stops retrying
+    pub expire: u32,
+}
+
+impl Default for AlertmanagerConfig {
+    fn default() -> Self {
+        let priorities = vec![
+            ("critical", Priority::High),
+            ("warning", Priority::Normal),
+            ("info", Priority::Low),
+        ];
+        Self {
+            title: "[{status}] {commonLabels.alertname}".to_string(),
+            message: "{alerts}".to_string(),
+            user: None,
+            severity_label: "severity".to_string(),
+            priorities: priorities
+                .into_iter()
+                .map(|(s, p)| (s.to_string(), PriorityValue(p)))
+                .collect(),
+            resolved_priority: None,
+            retry: 60,
+            expire: 3600,
+        }
+    }
+}
+
+/// [`Priority`] written as number or string in configuration e.g. `2` or `"2"`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PriorityValue(pub Priority);
+
+impl<'de> Deserialize<'de> for PriorityValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(i64),
+            String(String),
+        }
+        let raw = match Raw::deserialize(deserializer)? {
+            Raw::Number(n) => n.to_string(),
+            Raw::String(s) => s,
+        };
+        Priority::from_str(&raw)
+            .map(PriorityValue)
+            .map_err(|_| D::Error::custom(format!("invalid priority: {}", raw)))
+    }
+}
+
+impl Config {
+    /// Loads configuration from file, or defaults without one
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let path = match path {
+            Some(p) => p,
+            None => return Ok(Self::default()),
+        };
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed to read config {}", path.display()))?;
+        let config = toml::from_str(&content)
+            .with_context(|| format!("failed to parse config {}", path.display()))?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Config, PriorityValue};
+    use pullover::Priority;
+
+    #[test]
+    fn test_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [[serve.clients]]
+            name = "ci"
+            token = "secret"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(1, config.serve.clients.len());
+        assert_eq!("ci", config.serve.clients[0].name);
+        assert_eq!("secret", config.serve.clients[0].token);
+
+        let config: Config = toml::from_str("").unwrap();
+        assert!(config.serve.clients.is_empty());
+    }
+
+    #[test]
+    fn test_alertmanager_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [alertmanager]
+            priorities = { critical = 2, warning = "1" }
+            resolved_priority = -1
+            "#,
+        )
+        .unwrap();
+        let alertmanager = config.alertmanager;
+        assert_eq!(Priority::Emergency, alertmanager.priorities["critical"].0);
+        assert_eq!(Priority::High, alertmanager.priorities["warning"].0);
+        assert_eq!(
+            Some(PriorityValue(Priority::Low)),
+            alertmanager.resolved_priority
+        );
+        assert_eq!("{alerts}", alertmanager.message);
+
+        assert!(toml::from_str::<Config>("[alertmanager]\nresolved_priority = 3").is_err());
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::VecDeque;
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use pullover::MESSAGE_MAX_LENGTH;
+use structopt::clap::AppSettings;
+use structopt::StructOpt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::process::Command;
+
+use crate::{input, secret, Opts};
+
+/// Options of `po2 exec`
+#[derive(StructOpt)]
+#[structopt(setting = AppSettings::TrailingVarArg)]
+pub struct ExecOpts {
+    /// number of trailing output lines included in the notification
+    #[structopt(long, default_value = "10")]
+    lines: usize,
+    /// priority when the command fails e.g. -2, -1, 0, 1, 2 <https://pushover.net/api#priority>
+    #[structopt(long)]
+    failure_priority: Option<String>,
+    /// sound when the command fails <https://pushover.net/api#sounds>
+    #[structopt(long)]
+    failure_sound: Option<String>,
+    /// command to run and its arguments
+    #[structopt(required = true)]
+    command: Vec<String>,
+}
+
+/// Index of stdout in [`Tail::partials`]
+const STDOUT: usize = 0;
+/// Index of stderr in [`Tail::partials`]
+const STDERR: usize = 1;
+
+/// Last lines of output from both stdout and stderr of the child process
+#[derive(Default)]
+struct Tail {
+    lines: VecDeque<String>,
+    /// Incomplete last line of each stream, so interleaved output is not spliced
+    partials: [Vec<u8>; 2],
+    capacity: usize,
+}
+
+impl Tail {
+    fn push(&mut self, stream: usize, bytes: &[u8]) {
+        for b in bytes {
+            if *b == b'\n' {
+                let partial = &mut self.partials[stream];
+                let line = String::from_utf8_lossy(partial).trim_end().to_string();
+                partial.clear();
+                self.lines.push_back(line);
+                while self.lines.len() > self.capacity {
+                    self.lines.pop_front();
+                }
+            } else {
+                self.partials[stream].push(*b);
+            }
+        }
+    }
+
+    fn text(&self) -> String {
+        let mut lines: Vec<String> = self.lines.iter().cloned().collect();
+        for partial in self.partials.iter().filter(|p| !p.is_empty()) {
+            lines.push(String::from_utf8_lossy(partial).to_string());
+        }
+        let skip = lines.len().saturating_sub(self.capacity);
+        lines.split_off(skip).join("\n")
+    }
+}
+
+/// Runs command, passes its stdio through, and notifies when it exits
+///
+/// Returns exit code of the command. Options are validated before the command starts, so that
+/// nothing but the command itself decides the exit code.
+pub async fn run(opts: &Opts, exec: &ExecOpts) -> anyhow::Result<i32> {
+    let command_line = exec.command.join(" ");
+    opts.notification("")?;
+    let failure_priority = exec
+        .failure_priority
+        .as_deref()
+        .map(input::parse_priority)
+        .transpose()?;
+    let failure_sound = exec
+        .failure_sound
+        .as_deref()
+        .map(input::parse_sound)
+        .transpose()?;
+
+    // let the child process handle Ctrl-C, we still have to send the notification
+    tokio::spawn(async { while tokio::signal::ctrl_c().await.is_ok() {} });
+
+    let started = Instant::now();
+    let mut child = secret::hide(Command::new(&exec.command[0]).args(&exec.command[1..]))
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let tail = Arc::new(Mutex::new(Tail {
+        capacity: exec.lines,
+        ..Default::default()
+    }));
+    let stdout = child
+        .stdout
+        .take()
+        .map(|o| tokio::spawn(forward(o, tokio::io::stdout(), Arc::clone(&tail), STDOUT)));
+    let stderr = child
+        .stderr
+        .take()
+        .map(|e| tokio::spawn(forward(e, tokio::io::stderr(), Arc::clone(&tail), STDERR)));
+
+    let status = child.wait().await?;
+    for handle in stdout.into_iter().chain(stderr) {
+        match handle.await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => eprintln!("po2: failed to forward output: {}", e),
+            Err(e) => eprintln!("po2: failed to forward output: {}", e),
+        }
+    }
+    let duration = started.elapsed();
+
+    let code = exit_code(&status);
+    let host = hostname::get()
+        .map(|h| h.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let output = tail.lock().map(|t| t.text()).unwrap_or_default();
+    let message = build_message(code, duration, &host, &output);
+
+    // options are validated already, but the exit code of the command must survive anyway
+    let mut notification = match opts.notification(&message) {
+        Ok(n) => n,
+        Err(e) => {
+            eprintln!("po2: failed to send notification: {:#}", e);
+            return Ok(code);
+        }
+    };
+    let title;
+    if opts.title.is_none() {
+        title = input::fit_title(if status.success() {
+            format!("✅ {} succeeded", command_line)
+        } else {
+            format!("❌ {} failed", command_line)
+        });
+        notification.request.title = Some(title.as_str().into());
+    }
+    if !status.success() {
+        if failure_priority.is_some() {
+            notification.request.priority = failure_priority;
+        }
+        if failure_sound.is_some() {
+            notification.request.sound = failure_sound;
+        }
+    }
+    // exit code of the command matters more to the caller than a failed notification
+    if let Err(e) = opts.send(notification).await {
+        eprintln!("po2: failed to send notification: {:#}", e);
+    }
+
+    Ok(code)
+}
+
+/// Copies output of child process to our own, keeping a copy of `stream` in [`Tail`]
+async fn forward<R, W>(
+    mut from: R,
+    mut to: W,
+    tail: Arc<Mutex<Tail>>,
+    stream: usize,
+) -> std::io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buffer = [0u8; 8192];
+    loop {
+        let n = from.read(&mut buffer).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        to.write_all(&buffer[..n]).await?;
+        to.flush().await?;
+        if let Ok(mut t) = tail.lock() {
+            t.push(stream, &buffer[..n]);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn exit_code(status: &ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+    // follow the shell convention for processes killed by signal
+    status
+        .code()
+        .or_else(|| status.signal().map(|s| 128 + s))
+        .unwrap_or(1)
+}
+
+#[cfg(not(unix))]
+fn exit_code(status: &ExitStatus) -> i32 {
+    status.code().unwrap_or(1)
+}
+
+/// Formats duration in a human-friendly way e.g. `1h 2m 3s`
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}h {}m {}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds)
+    } else {
+        format!("{}.{:03}s", seconds, duration.subsec_millis())
+    }
+}
+
+/// Builds message with exit code, duration, host and as much trailing output as fits
+fn build_message(code: i32, duration: Duration, host: &str, output: &str) -> String {
+    let header = format!(
+        "exit code: {}\nduration: {}\nhost: {}",
+        code,
+        format_duration(duration),
+        host
+    );
+    if output.is_empty() {
+        return header;
+    }
+    // the end of output is usually the most interesting part, so cut from the front
+    let available = MESSAGE_MAX_LENGTH.saturating_sub(header.chars().count() + 2);
+    let length = output.chars().count();
+    let output: String = output
+        .chars()
+        .skip(length.saturating_sub(available))
+        .collect();
+    format!("{}\n\n{}", header, output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pullover::MESSAGE_MAX_LENGTH;
+    use structopt::StructOpt;
+
+    use crate::exec::{build_message, format_duration, run, ExecOpts, Tail, STDERR, STDOUT};
+    use crate::output::ExitCode;
+    use crate::Opts;
+
+    #[test]
+    fn test_tail() {
+        let mut tail = Tail {
+            capacity: 2,
+            ..Default::default()
+        };
+        tail.push(STDOUT, b"one\ntwo\nthr");
+        assert_eq!("two\nthr", tail.text());
+        tail.push(STDOUT, b"ee\nfour\n");
+        assert_eq!("three\nfour", tail.text());
+
+        let mut tail = Tail {
+            capacity: 3,
+            ..Default::default()
+        };
+        tail.push(STDOUT, b"out");
+        tail.push(STDERR, b"err\n");
+        tail.push(STDOUT, b"put\n");
+        assert_eq!("err\noutput", tail.text());
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!("1.500s", format_duration(Duration::from_millis(1500)));
+        assert_eq!("2m 3s", format_duration(Duration::from_secs(123)));
+        assert_eq!("1h 0m 1s", format_duration(Duration::from_secs(3601)));
+    }
+
+    #[test]
+    fn test_build_message() {
+        let message = build_message(1, Duration::from_secs(3), "host", "");
+        assert_eq!("exit code: 1\nduration: 3.000s\nhost: host", message);
+
+        let output = format!("{}end", "x".repeat(MESSAGE_MAX_LENGTH));
+        let message = build_message(0, Duration::from_secs(3), "host", &output);
+        assert_eq!(MESSAGE_MAX_LENGTH, message.chars().count());
+        assert!(message.ends_with("end"));
+    }
+
+    #[tokio::test]
+    async fn test_run_invalid() {
+        let opts = Opts::from_iter(&["po2", "-t", "token", "-u", "user"]);
+        let marker = std::env::temp_dir().join(format!("po2-exec-{}", std::process::id()));
+        let script = format!("touch {}", marker.display());
+        let cases = vec![
+            vec!["exec", "--failure-priority", "9", "--", "sh", "-c", &script],
+            vec!["exec", "--failure-sound", "nope", "--", "sh", "-c", &script],
+        ];
+        for args in cases {
+            let exec = ExecOpts::from_iter(&args);
+            let error = run(&opts, &exec).await.unwrap_err();
+            assert_eq!(ExitCode::Validation, ExitCode::of(&error), "{:?}", error);
+            assert!(!marker.exists(), "command ran with {:?}", args);
+        }
+
+        let opts = Opts::from_iter(&["po2", "-t", "token", "-u", "user", "--device", "a b"]);
+        let exec = ExecOpts::from_iter(&["exec", "--", "sh", "-c", &script]);
+        assert!(run(&opts, &exec).await.is_err());
+        assert!(!marker.exists());
+    }
+}
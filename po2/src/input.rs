@@ -0,0 +1,257 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::Context;
+use pullover::{Priority, Sound, MESSAGE_MAX_LENGTH, TITLE_MAX_LENGTH};
+
+use crate::output::Invalid;
+
+/// Appended to a message which has been cut down to [`MESSAGE_MAX_LENGTH`]
+const TRUNCATION_MARKER: &str = "[…]";
+
+/// Reads message body from argument, file or stdin
+///
+/// `-` or no message at all reads from stdin.
+pub fn read_message(message: Option<&str>, file: Option<&Path>) -> anyhow::Result<String> {
+    let message = match (message, file) {
+        (_, Some(p)) => fs::read_to_string(p)?,
+        (Some(m), None) if m != "-" => m.to_string(),
+        _ => {
+            let mut buffer = String::new();
+            io::stdin().read_to_string(&mut buffer)?;
+            buffer
+        }
+    };
+    // piped input usually ends with a newline which is meaningless in a notification
+    let message = message.trim_end_matches(&['\r', '\n'][..]).to_string();
+    if message.is_empty() {
+        return Err(Invalid("message is empty".into()).into());
+    }
+    Ok(message)
+}
+
+/// Opens file, or stdin when it is omitted or `-`
+pub fn open(path: Option<&Path>) -> anyhow::Result<Box<dyn Read>> {
+    match path {
+        Some(p) if p.as_os_str() != "-" => {
+            let file = File::open(p).with_context(|| format!("failed to open {}", p.display()))?;
+            Ok(Box::new(file))
+        }
+        _ => Ok(Box::new(io::stdin())),
+    }
+}
+
+/// Fits message into [`MESSAGE_MAX_LENGTH`], truncating it with a marker or failing
+pub fn fit_message(message: String, truncate: bool) -> anyhow::Result<String> {
+    let length = message.chars().count();
+    if length <= MESSAGE_MAX_LENGTH {
+        return Ok(message);
+    }
+    if !truncate {
+        return Err(Invalid(format!(
+            "message has {} characters, exceeding the limit of {} characters, pass --truncate to cut it down",
+            length,
+            MESSAGE_MAX_LENGTH
+        ))
+        .into());
+    }
+    Ok(cut(&message, MESSAGE_MAX_LENGTH))
+}
+
+/// Fits HTML message into [`MESSAGE_MAX_LENGTH`] like [`fit_message`], but never cuts a tag or entity in half
+pub fn fit_html(message: String, truncate: bool) -> anyhow::Result<String> {
+    if !truncate || message.chars().count() <= MESSAGE_MAX_LENGTH {
+        return fit_message(message, truncate);
+    }
+    Ok(cut_html(&message, MESSAGE_MAX_LENGTH))
+}
+
+/// Fits message converted by `convert` e.g. from Markdown to HTML into [`MESSAGE_MAX_LENGTH`]
+///
+/// The source rather than the output is cut down, so no tag or entity is cut in half.
+pub fn fit_converted<F>(message: String, truncate: bool, convert: F) -> anyhow::Result<String>
+where
+    F: Fn(&str) -> String,
+{
+    let converted = convert(&message);
+    if !truncate || converted.chars().count() <= MESSAGE_MAX_LENGTH {
+        return fit_message(converted, truncate);
+    }
+    let marker = TRUNCATION_MARKER.chars().count();
+    let mut keep = message.chars().count().min(MESSAGE_MAX_LENGTH);
+    loop {
+        let converted = convert(&cut(&message, keep));
+        let length = converted.chars().count();
+        if length <= MESSAGE_MAX_LENGTH {
+            return Ok(converted);
+        }
+        // markup only adds characters, so the source is at least as long as the excess
+        let excess = length - MESSAGE_MAX_LENGTH;
+        if keep <= marker + excess {
+            return Err(Invalid("message cannot be cut down to fit the limit".into()).into());
+        }
+        keep -= excess;
+    }
+}
+
+/// Fits title into [`TITLE_MAX_LENGTH`], truncating it with a marker
+pub fn fit_title(title: String) -> String {
+    if title.chars().count() <= TITLE_MAX_LENGTH {
+        return title;
+    }
+    cut(&title, TITLE_MAX_LENGTH)
+}
+
+/// Cuts text down to `max` characters including the marker
+fn cut(text: &str, max: usize) -> String {
+    let keep = max - TRUNCATION_MARKER.chars().count();
+    let mut truncated: String = text.chars().take(keep).collect();
+    truncated.push_str(TRUNCATION_MARKER);
+    truncated
+}
+
+/// Parses priority e.g. `-2` or `emergency` <https://pushover.net/api#priority>
+pub fn parse_priority(priority: &str) -> Result<Priority, Invalid> {
+    Priority::from_str(priority).map_err(|_| Invalid(format!("unknown priority {}", priority)))
+}
+
+/// Parses sound e.g. `pushover` <https://pushover.net/api#sounds>
+pub fn parse_sound(sound: &str) -> Result<Sound, Invalid> {
+    Sound::from_str(sound).map_err(|_| Invalid(format!("unknown sound {}", sound)))
+}
+
+/// Cuts HTML down like [`cut`], dropping an unclosed tag or entity at the end
+fn cut_html(text: &str, max: usize) -> String {
+    let keep = max - TRUNCATION_MARKER.chars().count();
+    let mut truncated: String = text.chars().take(keep).collect();
+    if let Some(i) = truncated.rfind('<') {
+        if !truncated[i..].contains('>') {
+            truncated.truncate(i);
+        }
+    }
+    if let Some(i) = truncated.rfind('&') {
+        if truncated[i + 1..]
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '#')
+        {
+            truncated.truncate(i);
+        }
+    }
+    truncated.push_str(TRUNCATION_MARKER);
+    truncated
+}
+
+/// Guesses whether message is tabular or log output, which reads better in monospace
+pub fn looks_monospace(message: &str) -> bool {
+    let lines: Vec<&str> = message.lines().filter(|l| !l.trim().is_empty()).collect();
+    if lines.len() < 2 {
+        return false;
+    }
+    let hits = lines
+        .iter()
+        .filter(|l| l.contains('\t') || l.trim().contains("  ") || starts_with_time(l))
+        .count();
+    hits * 2 >= lines.len()
+}
+
+/// e.g. `2021-08-20 12:00:00`, `[12:00:00]` or `12:00:00.123`
+fn starts_with_time(line: &str) -> bool {
+    let line = line.trim_start_matches('[');
+    let prefix: Vec<char> = line.chars().take(10).collect();
+    let digits = prefix.iter().filter(|c| c.is_ascii_digit()).count();
+    let separators = prefix.iter().filter(|c| **c == '-' || **c == ':').count();
+    matches!(prefix.first(), Some(c) if c.is_ascii_digit()) && digits >= 4 && separators >= 2
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::input::{
+        fit_converted, fit_html, fit_message, fit_title, looks_monospace, TRUNCATION_MARKER,
+    };
+    use pullover::markdown::to_html;
+    use pullover::{MESSAGE_MAX_LENGTH, TITLE_MAX_LENGTH};
+
+    #[test]
+    fn test_fit_message() {
+        assert_eq!("short", fit_message("short".into(), false).unwrap());
+
+        let long = "あ".repeat(MESSAGE_MAX_LENGTH + 1);
+        assert!(fit_message(long.clone(), false).is_err());
+
+        let truncated = fit_message(long, true).unwrap();
+        assert_eq!(MESSAGE_MAX_LENGTH, truncated.chars().count());
+        assert!(truncated.ends_with(TRUNCATION_MARKER));
+    }
+
+    #[test]
+    fn test_fit_converted() {
+        let link = "[a long link](https://example.com/?a=1&b=2)";
+        let markdown = format!(
+            "{}{}",
+            "x".repeat(MESSAGE_MAX_LENGTH - link.len() - 2),
+            link
+        );
+        assert!(markdown.chars().count() < MESSAGE_MAX_LENGTH);
+        assert!(to_html(&markdown).chars().count() > MESSAGE_MAX_LENGTH);
+        assert!(fit_converted(markdown.clone(), false, to_html).is_err());
+
+        let html = fit_converted(markdown, true, to_html).unwrap();
+        assert!(html.chars().count() <= MESSAGE_MAX_LENGTH);
+        assert!(html.ends_with(TRUNCATION_MARKER), "{}", html);
+        assert!(!html.contains('<'), "{}", html);
+        assert!(!html.contains('&'), "{}", html);
+
+        let short = fit_converted("**bold**".into(), false, to_html).unwrap();
+        assert_eq!("<b>bold</b>", short);
+    }
+
+    #[test]
+    fn test_fit_html() {
+        assert_eq!(
+            "<b>short</b>",
+            fit_html("<b>short</b>".into(), false).unwrap()
+        );
+
+        let keep = MESSAGE_MAX_LENGTH - TRUNCATION_MARKER.chars().count();
+        let cases = vec![
+            (
+                r#"<a href="https://example.com/">link</a>"#,
+                "x".repeat(keep - 5),
+            ),
+            ("&amp; b", "x".repeat(keep - 3)),
+        ];
+        for (tail, head) in cases {
+            let html = format!("{}{}", head, tail);
+            assert!(fit_html(html.clone(), false).is_err());
+            let truncated = fit_html(html, true).unwrap();
+            assert_eq!(format!("{}{}", head, TRUNCATION_MARKER), truncated);
+        }
+
+        let html = format!("{}a &amp; more text", "x".repeat(keep - 7));
+        let truncated = fit_html(html, true).unwrap();
+        assert!(truncated.ends_with(&format!("a &amp;{}", TRUNCATION_MARKER)));
+    }
+
+    #[test]
+    fn test_fit_title() {
+        assert_eq!("short", fit_title("short".into()));
+
+        let truncated = fit_title("x".repeat(TITLE_MAX_LENGTH + 1));
+        assert_eq!(TITLE_MAX_LENGTH, truncated.chars().count());
+        assert!(truncated.ends_with(TRUNCATION_MARKER));
+    }
+
+    #[test]
+    fn test_looks_monospace() {
+        assert!(!looks_monospace("hello world"));
+        assert!(!looks_monospace("build finished\nall good"));
+        assert!(looks_monospace("NAME    READY\nweb     1/1\ndb      1/1"));
+        assert!(looks_monospace("a\tb\nc\td"));
+        assert!(looks_monospace(
+            "2021-08-20 12:00:00 INFO started\n2021-08-20 12:00:01 WARN slow"
+        ));
+        assert!(looks_monospace("[12:00:00] started\n[12:00:01] stopped"));
+    }
+}
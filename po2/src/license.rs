@@ -0,0 +1,63 @@
+use std::str::FromStr;
+
+use anyhow::Context;
+use pullover::license::{self, Assignee, OS};
+use structopt::StructOpt;
+
+use crate::Opts;
+
+/// Options of `po2 license`
+#[derive(StructOpt)]
+pub enum LicenseOpts {
+    /// Print remaining license credits <https://pushover.net/api/licensing#credits>
+    Credits,
+    /// Assign a license credit to a user <https://pushover.net/api/licensing#assign>
+    Assign {
+        /// user key of an existing user
+        #[structopt(long, required_unless = "email", conflicts_with = "email")]
+        user: Option<String>,
+        /// email of a user, who is invited to create an account if there is none
+        #[structopt(long)]
+        email: Option<String>,
+        /// operating system the license is limited to e.g. android, ios, desktop
+        #[structopt(long)]
+        os: Option<String>,
+    },
+}
+
+/// Runs `po2 license`
+pub async fn run(opts: &Opts, license: &LicenseOpts) -> anyhow::Result<()> {
+    let token = opts.token()?;
+    match license {
+        LicenseOpts::Credits => println!("{}", license::credits(token).await?),
+        LicenseOpts::Assign { user, email, os } => {
+            let assignee = match (user, email) {
+                (Some(u), _) => Assignee::User(u),
+                (None, Some(e)) => Assignee::Email(e),
+                (None, None) => unreachable!("required by structopt"),
+            };
+            let os = os
+                .as_deref()
+                .map(|o| OS::from_str(o).with_context(|| format!("invalid OS: {}", o)))
+                .transpose()?;
+            license::assign(token, assignee, os).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use structopt::StructOpt;
+
+    use crate::license::LicenseOpts;
+
+    #[test]
+    fn test_assign_opts() {
+        let parse = |args: &[&str]| LicenseOpts::from_iter_safe(args);
+        assert!(parse(&["license", "assign", "--user", "u"]).is_ok());
+        assert!(parse(&["license", "assign", "--email", "user@example.com"]).is_ok());
+        assert!(parse(&["license", "assign"]).is_err());
+        assert!(parse(&["license", "assign", "--user", "u", "--email", "e"]).is_err());
+    }
+}
@@ -12,41 +12,86 @@
 
 //! po2 is a command line application based on Pullover
 
-use pullover::{Attachment, Monospace, Notification, Priority, Sound, HTML};
+use pullover::notifier::{DryRun, Http, Notifier};
+use pullover::{Attachment, DeviceSet, Format, Notification, Response};
+use std::ffi::OsString;
 use std::path::PathBuf;
 use std::str::FromStr;
+use structopt::clap::ErrorKind;
 use structopt::StructOpt;
+use tracing_subscriber::EnvFilter;
+
+use crate::config::Config;
+use crate::output::{ExitCode, Invalid, Output, Rejected};
+
+mod alertmanager;
+mod config;
+mod exec;
+mod input;
+mod license;
+mod migrate;
+mod output;
+mod receive;
+mod secret;
+mod serve;
+mod tail;
+mod team;
+mod template;
+mod timestamp;
+mod wait_pid;
+mod webhook;
 
 #[derive(StructOpt)]
-#[structopt(about, author)]
+#[structopt(about, author, after_help = output::EXIT_CODES)]
 struct Opts {
-    /// your application's API token <https://pushover.net/api#identifiers>
-    #[structopt(short, long, env = "PUSHOVER_TOKEN")]
-    token: String,
-    /// the user / group key (not e-mail address) of your user (or you) <https://pushover.net/api#identifiers>
-    #[structopt(short, long, env = "PUSHOVER_USER")]
-    user: String,
-    /// your message <https://pushover.net/api#messages>
+    /// your application's API token, otherwise read from $CREDENTIALS_DIRECTORY/pushover-token, token_cmd in configuration or keyring <https://pushover.net/api#identifiers>
+    #[structopt(short, long, env = "PUSHOVER_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+    /// read your application's API token from file, which keeps it out of process list and environment
+    #[structopt(long, env = "PUSHOVER_TOKEN_FILE")]
+    token_file: Option<PathBuf>,
+    /// the user / group key (not e-mail address) of your user (or you), otherwise read from $CREDENTIALS_DIRECTORY/pushover-user, user_cmd in configuration or keyring <https://pushover.net/api#identifiers>
+    #[structopt(short, long, env = "PUSHOVER_USER", hide_env_values = true)]
+    user: Option<String>,
+    /// read the user / group key from file
+    #[structopt(long, env = "PUSHOVER_USER_FILE")]
+    user_file: Option<PathBuf>,
+    /// your message, read from stdin when omitted or "-" <https://pushover.net/api#messages>
     #[structopt(short, long)]
-    message: String,
-    /// verbose
+    message: Option<String>,
+    /// read your message from file
+    #[structopt(long, conflicts_with = "message")]
+    message_file: Option<PathBuf>,
+    /// truncate message exceeding the limit instead of failing <https://pushover.net/api#limits>
+    #[structopt(long)]
+    truncate: bool,
+    /// enable monospace when message looks like tabular or log output
+    #[structopt(long)]
+    auto_monospace: bool,
+    /// verbose, printing response and spans of requests to stderr, filtered by RUST_LOG if set
     #[structopt(short, long)]
     verbose: bool,
     /// To enable HTML formatting <https://pushover.net/api#html>
-    #[structopt(long)]
+    #[structopt(long, conflicts_with = "monospace")]
     html: bool,
     /// To enable monospace messages <https://pushover.net/api#html>
     #[structopt(long)]
     monospace: bool,
-    /// your user's device name to send the message directly to that device, rather than all of the user's devices <https://pushover.net/api#identifiers>
+    /// convert your message from Markdown to HTML <https://pushover.net/api#html>
+    #[structopt(long, conflicts_with = "monospace")]
+    markdown: bool,
+    /// your user's device name to send the message directly to that device, rather than all of the user's devices, repeated or separated by comma for multiple devices <https://pushover.net/api#identifiers>
+    #[structopt(long, number_of_values = 1)]
+    device: Vec<String>,
+    /// check the devices are registered by the user before sending <https://pushover.net/api#verification>
     #[structopt(long)]
-    device: Option<String>,
+    check_devices: bool,
     /// your message's title, otherwise your app's name is used <https://pushover.net/api#messages>
     #[structopt(long)]
     title: Option<String>,
-    /// a Unix timestamp of your message's date and time to display to the user, rather than the time your message is received by our API <https://pushover.net/api#timestamp>
-    #[structopt(long)]
-    timestamp: Option<u64>,
+    /// your message's date and time to display to the user, rather than the time your message is received by our API, in RFC 3339, @<unix> or relative like -15m <https://pushover.net/api#timestamp>
+    #[structopt(long, allow_hyphen_values = true)]
+    timestamp: Option<timestamp::Timestamp>,
     /// attach file as notification attachment
     #[structopt(short, long)]
     file: Option<PathBuf>,
@@ -62,56 +107,415 @@ struct Opts {
     /// a title for your supplementary URL, otherwise just the URL is shown <https://pushover.net/api#urls>
     #[structopt(long)]
     url_title: Option<String>,
+    /// print the form fields which would be sent instead of sending them
+    #[structopt(long)]
+    dry_run: bool,
+    /// output format of sending a notification without subcommand, "text" or "json" with status, request ID, receipt, errors and limits
+    #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+    output: Output,
+    /// configuration file in TOML
+    #[structopt(long, env = "PO2_CONFIG")]
+    config: Option<PathBuf>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let opts: Opts = Opts::from_args();
-
-    let mut notification = Notification::new(&opts.token, &opts.user, &opts.message);
+#[derive(StructOpt)]
+enum Command {
+    /// Run a command and send a notification when it exits
+    Exec(exec::ExecOpts),
+    /// Wait for a running process to exit and send a notification
+    WaitPid(wait_pid::WaitPidOpts),
+    /// Follow a log file and send notifications for lines matching a pattern
+    Tail(tail::TailOpts),
+    /// Manage members of Pushover for Teams <https://pushover.net/api/teams>
+    Team(team::TeamOpts),
+    /// Run HTTP relay which forwards notifications with the token kept on the server side
+    Serve(serve::ServeOpts),
+    /// Work with webhook routes of the relay
+    Webhook(webhook::WebhookOpts),
+    /// Check and assign license credits <https://pushover.net/api/licensing>
+    License(license::LicenseOpts),
+    /// Migrate user keys listed in CSV into subscribed user keys <https://pushover.net/api/subscriptions#migration>
+    Migrate(migrate::MigrateOpts),
+    /// Receive messages as a desktop device and run hooks for them <https://pushover.net/api/client>
+    Receive(receive::ReceiveOpts),
+}
 
-    // set extra options
-    if let Some(ref d) = opts.device {
-        notification.request.device = Some(d.into());
+impl Opts {
+    /// API token, required by all commands sending notifications
+    fn token(&self) -> anyhow::Result<&str> {
+        self.token.as_deref().ok_or_else(|| {
+            Invalid("--token, --token-file or PUSHOVER_TOKEN is required".into()).into()
+        })
     }
-    if let Some(ref t) = opts.title {
-        notification.request.title = Some(t.into());
+
+    /// User key, required by all commands sending notifications
+    fn user(&self) -> anyhow::Result<&str> {
+        self.user.as_deref().ok_or_else(|| {
+            Invalid("--user, --user-file or PUSHOVER_USER is required".into()).into()
+        })
     }
-    if let Some(ref t) = opts.timestamp {
-        notification.request.timestamp = Some(*t);
+
+    /// Whether subcommand needs token and user key, `po2 webhook` and `po2 receive` do not
+    fn needs_secrets(&self) -> bool {
+        !matches!(
+            self.command,
+            Some(Command::Webhook(_)) | Some(Command::Receive(_))
+        )
     }
-    if let Some(ref p) = opts.priority {
-        notification.request.priority = Some(Priority::from_str(p)?);
+
+    /// Reads token and user key from files, systemd credentials, commands or keyring
+    ///
+    /// Files given by `--token-file` and `--user-file` take precedence over `--token` and `--user`.
+    async fn resolve_secrets(&mut self, config: &Config) -> anyhow::Result<()> {
+        let token = secret::Source {
+            file: self.token_file.as_deref(),
+            credential: "pushover-token",
+            command: config.token_cmd.as_deref(),
+            keyring: "token",
+        };
+        if self.token.is_none() || token.file.is_some() {
+            if let Some(t) = token.read().await? {
+                self.token = Some(t);
+            }
+        }
+        let user = secret::Source {
+            file: self.user_file.as_deref(),
+            credential: "pushover-user",
+            command: config.user_cmd.as_deref(),
+            keyring: "user",
+        };
+        if self.user.is_none() || user.file.is_some() {
+            if let Some(u) = user.read().await? {
+                self.user = Some(u);
+            }
+        }
+        Ok(())
     }
-    if let Some(ref s) = opts.sound {
-        notification.request.sound = Some(Sound::from_str(s)?);
+
+    /// Converts message from Markdown to HTML if `--markdown` is set
+    fn convert(&self, message: String) -> String {
+        if self.markdown {
+            pullover::markdown::to_html(&message)
+        } else {
+            message
+        }
     }
-    if let Some(ref u) = opts.url {
-        notification.request.url = Some(u.into());
-        if let Some(ref t) = opts.url_title {
-            notification.request.url_title = Some(t.into());
+
+    /// Converts message and fits it into the limit, see [`input::fit_converted`] and [`input::fit_html`]
+    fn fit(&self, message: String, truncate: bool) -> anyhow::Result<String> {
+        if self.html && !self.markdown {
+            input::fit_html(message, truncate)
+        } else {
+            input::fit_converted(message, truncate, |m| self.convert(m.to_string()))
         }
     }
 
-    if opts.html {
-        notification.request.html = Some(HTML::Enabled);
-        if opts.monospace {
-            notification.request.monospace = Some(Monospace::Enabled);
+    /// Creates a [`Notification`] with extra options applied
+    fn notification<'a>(&'a self, message: &'a str) -> anyhow::Result<Notification<'a>> {
+        let mut notification = Notification::new(self.token()?, self.user()?, message);
+
+        // set extra options
+        if !self.device.is_empty() {
+            let devices = DeviceSet::from_str(&self.device.join(","))?;
+            notification.request.device = Some(devices);
+        }
+        if let Some(ref t) = self.title {
+            notification.request.title = Some(t.into());
+        }
+        if let Some(ref t) = self.timestamp {
+            notification.request.timestamp = Some(t.0);
+        }
+        if let Some(ref p) = self.priority {
+            notification.request.priority = Some(input::parse_priority(p)?);
         }
+        if let Some(ref s) = self.sound {
+            notification.request.sound = Some(input::parse_sound(s)?);
+        }
+        if let Some(ref u) = self.url {
+            notification.request.url = Some(u.into());
+            if let Some(ref t) = self.url_title {
+                notification.request.url_title = Some(t.into());
+            }
+        }
+
+        let html = self.html || self.markdown;
+        notification.request.format = match Format::from_flags(html, self.monospace)? {
+            Format::Plain if self.auto_monospace && input::looks_monospace(message) => {
+                Some(Format::Monospace)
+            }
+            Format::Plain => None,
+            f => Some(f),
+        };
+
+        Ok(notification)
     }
 
-    // send request with file as attachment
-    let attachment;
-    if let Some(p) = &opts.file {
-        attachment = Attachment::from_path(p).await?;
-        notification.attach(&attachment);
+    /// Sends [`Notification`] with file as attachment if any
+    ///
+    /// Response rejected by Pushover API is returned as [`Rejected`] error.
+    async fn send(&self, notification: Notification<'_>) -> anyhow::Result<Response> {
+        let mut notification = notification;
+
+        // send request with file as attachment
+        let attachment;
+        if let Some(p) = &self.file {
+            attachment = Attachment::from_path(p).await?;
+            notification.attach(&attachment);
+        }
+
+        if self.check_devices && !self.dry_run {
+            if let Some(ref d) = notification.request.device {
+                let user = pullover::user::validate(self.token()?, self.user()?, None).await?;
+                // devices of members are not listed for a group key
+                if user.group {
+                    eprintln!("po2: --check-devices is skipped for a group key");
+                } else {
+                    d.check(&user.devices)?;
+                }
+            }
+        }
+
+        // send request
+        // keep stdout for the JSON object only
+        let notifier: Box<dyn Notifier> = match (self.dry_run, self.output) {
+            (true, Output::Text) => Box::new(DryRun::with_writer(std::io::stdout())),
+            (true, Output::Json) => Box::new(DryRun::new()),
+            (false, _) => Box::new(Http),
+        };
+        let res = notifier.notify(&notification).await?;
+        if self.verbose && self.output == Output::Text {
+            println!("{:?}", res);
+        }
+        if res.status != 1 {
+            return Err(Rejected(res).into());
+        }
+        Ok(res)
     }
+}
 
-    // send request
-    let res = notification.send().await?;
+#[tokio::main]
+async fn main() {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let mut opts = match Opts::from_iter_safe(&args) {
+        Ok(o) => o,
+        Err(e)
+            if matches!(
+                e.kind,
+                ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed
+            ) =>
+        {
+            e.exit()
+        }
+        Err(e) => {
+            let e = anyhow::Error::from(e);
+            if output::wants_json(&args) {
+                println!("{}", output::report_error(&e));
+            }
+            eprintln!("{}", e);
+            std::process::exit(ExitCode::of(&e) as i32);
+        }
+    };
     if opts.verbose {
-        println!("{:?}", res);
+        let filter = EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| EnvFilter::new("pullover=debug,po2=debug"));
+        tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_writer(std::io::stderr)
+            .init();
+    }
+
+    if let Err(e) = run(&mut opts).await {
+        if opts.output == Output::Json {
+            println!("{}", output::report_error(&e));
+        }
+        eprintln!("Error: {:?}", e);
+        std::process::exit(ExitCode::of(&e) as i32);
+    }
+}
+
+/// Runs subcommand, or sends a notification without one
+async fn run(opts: &mut Opts) -> anyhow::Result<()> {
+    let config = Config::load(opts.config.as_deref())?;
+    // commands of secrets may prompt or fail, so only run them when needed
+    if opts.needs_secrets() {
+        opts.resolve_secrets(&config).await?;
+    }
+
+    let opts = &*opts;
+    match opts.command {
+        Some(Command::Exec(ref e)) => {
+            let code = exec::run(opts, e).await?;
+            std::process::exit(code);
+        }
+        Some(Command::WaitPid(ref w)) => wait_pid::run(opts, w).await?,
+        Some(Command::Tail(ref t)) => tail::run(opts, t).await?,
+        Some(Command::Serve(ref s)) => serve::run(opts, config, s).await?,
+        Some(Command::Webhook(ref w)) => webhook::run(&config, w)?,
+        Some(Command::Team(ref t)) => team::run(opts, t).await?,
+        Some(Command::License(ref l)) => license::run(opts, l).await?,
+        Some(Command::Migrate(ref m)) => migrate::run(opts, m).await?,
+        Some(Command::Receive(ref r)) => receive::run(&config, r).await?,
+        None => {
+            let message =
+                input::read_message(opts.message.as_deref(), opts.message_file.as_deref())?;
+            let message = opts.fit(message, opts.truncate)?;
+            let res = opts.send(opts.notification(&message)?).await?;
+            if opts.output == Output::Json {
+                println!("{}", output::report(&res, ExitCode::Success));
+            }
+        }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use mockito::{mock, Matcher};
+    use pullover::{Format, API_URL_ENV};
+    use structopt::StructOpt;
+
+    use crate::output::ExitCode;
+    use crate::{run, Opts};
+
+    #[test]
+    fn test_format() {
+        let opts = Opts::from_iter(&["po2", "-t", "token", "-u", "user", "--monospace"]);
+        let notification = opts.notification("message").unwrap();
+        assert_eq!(Some(Format::Monospace), notification.request.format);
+
+        let opts = Opts::from_iter(&["po2", "-t", "token", "-u", "user", "--auto-monospace"]);
+        let notification = opts.notification("a\tb\nc\td").unwrap();
+        assert_eq!(Some(Format::Monospace), notification.request.format);
+        let notification = opts.notification("message").unwrap();
+        assert_eq!(None, notification.request.format);
+
+        let args = ["po2", "-t", "token", "-u", "user", "--html", "--monospace"];
+        assert!(Opts::from_iter_safe(&args).is_err());
+    }
+
+    #[test]
+    fn test_markdown() {
+        let opts = Opts::from_iter(&["po2", "-t", "token", "-u", "user", "--markdown"]);
+        let message = opts.convert("**done** <3".to_string());
+        assert_eq!("<b>done</b> &lt;3", message);
+        let notification = opts.notification(&message).unwrap();
+        assert_eq!(Some(Format::HTML), notification.request.format);
+
+        let args = [
+            "po2",
+            "-t",
+            "token",
+            "-u",
+            "user",
+            "--markdown",
+            "--monospace",
+        ];
+        assert!(Opts::from_iter_safe(&args).is_err());
+    }
+
+    #[test]
+    fn test_device() {
+        let args = [
+            "po2",
+            "-t",
+            "token",
+            "-u",
+            "user",
+            "--device",
+            "iphone,nexus5",
+            "--device",
+            "desktop",
+            "--device",
+            "iphone",
+        ];
+        let opts = Opts::from_iter(&args);
+        let notification = opts.notification("message").unwrap();
+        let devices = notification.request.device.unwrap();
+        assert_eq!("iphone,nexus5,desktop", devices.to_string());
+
+        let opts = Opts::from_iter(&["po2", "-t", "token", "-u", "user", "--device", "my phone"]);
+        assert!(opts.notification("message").is_err());
+
+        let opts = Opts::from_iter(&["po2", "-t", "token", "-u", "user"]);
+        assert!(opts
+            .notification("message")
+            .unwrap()
+            .request
+            .device
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_send_rejected() {
+        std::env::set_var(API_URL_ENV, mockito::server_url());
+        let _m = mock("POST", "/1/messages.json")
+            .match_body(Matcher::Regex("rejected".into()))
+            .with_status(400)
+            .with_body(r#"{"status":0,"errors":["user identifier is invalid"],"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        let opts = Opts::from_iter(&["po2", "-t", "token", "-u", "user"]);
+        let error = opts
+            .send(opts.notification("rejected").unwrap())
+            .await
+            .unwrap_err();
+        assert_eq!(ExitCode::Rejected, ExitCode::of(&error));
+        assert_eq!("API error: user identifier is invalid", error.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_check_devices_of_group() {
+        std::env::set_var(API_URL_ENV, mockito::server_url());
+        let _v = mock("POST", "/1/users/validate.json")
+            .match_body(Matcher::UrlEncoded("user".into(), "group".into()))
+            .with_status(200)
+            .with_body(r#"{"status":1,"group":1,"devices":[],"licenses":[],"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        let _m = mock("POST", "/1/messages.json")
+            .match_body(Matcher::Regex("to group".into()))
+            .with_status(200)
+            .with_body(r#"{"status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        let args = [
+            "po2",
+            "-t",
+            "token",
+            "-u",
+            "group",
+            "--device",
+            "iphone",
+            "--check-devices",
+        ];
+        let opts = Opts::from_iter(&args);
+        let res = opts.send(opts.notification("to group").unwrap()).await;
+        assert_eq!(1, res.unwrap().status);
+    }
+
+    #[tokio::test]
+    async fn test_run_without_secrets() -> anyhow::Result<()> {
+        let config = std::env::temp_dir().join(format!("po2-config-{}.toml", std::process::id()));
+        std::fs::write(&config, "token_cmd = \"exit 1\"\nuser_cmd = \"exit 1\"\n")?;
+        let path = config.to_string_lossy().to_string();
+
+        let args = [
+            "po2",
+            "--config",
+            &path,
+            "webhook",
+            "test",
+            "nope",
+            "payload.json",
+        ];
+        let error = run(&mut Opts::from_iter(&args)).await.unwrap_err();
+        assert_eq!("no such route: nope", error.to_string());
+
+        let args = ["po2", "--config", &path, "--dry-run", "-m", "hello"];
+        let error = run(&mut Opts::from_iter(&args)).await.unwrap_err();
+        assert_eq!(ExitCode::Validation, ExitCode::of(&error), "{:?}", error);
+
+        std::fs::remove_file(&config)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context};
+use pullover::subscription;
+use pullover::Sound;
+use serde::{Deserialize, Serialize};
+use structopt::StructOpt;
+
+use crate::{input, Opts};
+
+/// Options of `po2 migrate`
+#[derive(StructOpt)]
+pub struct MigrateOpts {
+    /// subscription code of your application <https://pushover.net/api/subscriptions>
+    #[structopt(long)]
+    subscription: String,
+    /// CSV with a `user` column and optional `device` and `sound` columns, read from stdin when omitted or "-"
+    input: Option<PathBuf>,
+    /// CSV mapping user keys to subscribed user keys, written to stdout when omitted
+    #[structopt(long)]
+    mapping: Option<PathBuf>,
+}
+
+/// Row of input CSV
+#[derive(Debug, Deserialize)]
+struct Row {
+    user: String,
+    #[serde(default)]
+    device: Option<String>,
+    #[serde(default)]
+    sound: Option<String>,
+}
+
+/// Row of output CSV, with error when the user key is not migrated
+#[derive(Debug, Serialize)]
+struct Mapping<'a> {
+    user: &'a str,
+    subscribed_user_key: &'a str,
+    error: &'a str,
+}
+
+impl Row {
+    async fn migrate(&self, token: &str, subscription: &str) -> anyhow::Result<String> {
+        let sound = match self.sound {
+            Some(ref s) => {
+                Some(Sound::from_str(s).with_context(|| format!("invalid sound: {}", s))?)
+            }
+            None => None,
+        };
+        let res = subscription::migrate(
+            token,
+            subscription,
+            &self.user,
+            self.device.as_deref(),
+            sound,
+        )
+        .await?;
+        res.subscribed_user_key
+            .ok_or_else(|| anyhow!("no subscribed user key in response"))
+    }
+}
+
+/// Runs `po2 migrate`
+pub async fn run(opts: &Opts, migrate: &MigrateOpts) -> anyhow::Result<()> {
+    let token = opts.token()?;
+    let input = input::open(migrate.input.as_deref())?;
+    let output: Box<dyn Write> = match migrate.mapping {
+        Some(ref p) => {
+            Box::new(File::create(p).with_context(|| format!("failed to create {}", p.display()))?)
+        }
+        None => Box::new(io::stdout()),
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(input);
+    let mut writer = csv::Writer::from_writer(output);
+    let (mut total, mut failures) = (0, 0);
+    for (i, row) in reader.deserialize::<Row>().enumerate() {
+        total += 1;
+        let (user, result) = match row {
+            Ok(r) => (
+                r.user.clone(),
+                r.migrate(token, &migrate.subscription).await,
+            ),
+            Err(e) => (String::new(), Err(e.into())),
+        };
+        let (key, error) = match result {
+            Ok(k) => (k, String::new()),
+            Err(e) => {
+                failures += 1;
+                eprintln!("row {}: {:#}", i + 1, e);
+                (String::new(), format!("{:#}", e))
+            }
+        };
+        writer.serialize(Mapping {
+            user: &user,
+            subscribed_user_key: &key,
+            error: &error,
+        })?;
+        writer.flush()?;
+    }
+
+    if failures > 0 {
+        bail!("{} of {} rows failed", failures, total);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migrate::Row;
+
+    #[test]
+    fn test_rows() {
+        let csv =
+            "user,sound\nuQiRzpo4DXghDmr9QzzfQu27cmVRsG,bike\n azGDORePK8gMaC0QOYAMyEEuzJnyUi , \n";
+        let rows: Vec<Row> = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(csv.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(2, rows.len());
+        assert_eq!(Some("bike"), rows[0].sound.as_deref());
+        assert_eq!(None, rows[0].device);
+        assert_eq!("azGDORePK8gMaC0QOYAMyEEuzJnyUi", rows[1].user);
+        assert_eq!(None, rows[1].sound);
+
+        let missing: Result<Vec<Row>, _> = csv::Reader::from_reader("device\nphone\n".as_bytes())
+            .deserialize()
+            .collect();
+        assert!(missing.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_sound() {
+        let row = Row {
+            user: "uQiRzpo4DXghDmr9QzzfQu27cmVRsG".into(),
+            device: None,
+            sound: Some("trumpet".into()),
+        };
+        let err = row.migrate("token", "subscription").await.unwrap_err();
+        assert_eq!("invalid sound: trumpet", err.to_string());
+    }
+}
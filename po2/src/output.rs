@@ -0,0 +1,274 @@
+use std::ffi::OsStr;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::bail;
+use pullover::user::UserError;
+use pullover::{AttachmentError, DeviceError, FormatError, NotificationError, Response};
+use serde_json::{json, Value};
+use structopt::clap;
+
+/// Exit codes, documented in `--help`
+pub const EXIT_CODES: &str = "EXIT CODES:
+    0    notification sent
+    1    other error
+    2    validation error e.g. missing token, empty message or unknown device
+    3    notification rejected by Pushover API
+    4    network error e.g. connection failed or unexpected response
+    5    rate limited as the monthly message limit is reached
+    6    attachment error e.g. file not found or unknown MIME type";
+
+/// Format of output of sending a notification
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Output {
+    /// Nothing on success, error on stderr
+    Text,
+    /// JSON object on stdout with status, request ID, receipt, errors and limits
+    Json,
+}
+
+impl FromStr for Output {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Output::Text),
+            "json" => Ok(Output::Json),
+            _ => bail!("unknown output {}, expected text or json", s),
+        }
+    }
+}
+
+/// Exit code of po2, see [`EXIT_CODES`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExitCode {
+    /// Notification sent
+    Success = 0,
+    /// Other error
+    Other = 1,
+    /// Invalid options or input
+    Validation = 2,
+    /// Rejected by Pushover API
+    Rejected = 3,
+    /// Connection failed or unexpected response
+    Network = 4,
+    /// Monthly message limit reached
+    RateLimited = 5,
+    /// Attachment cannot be read or fetched
+    Attachment = 6,
+}
+
+impl ExitCode {
+    /// Maps error to exit code by the first known cause in its chain
+    pub fn of(error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if let Some(r) = cause.downcast_ref::<Rejected>() {
+                return if r.0.is_rate_limited() {
+                    ExitCode::RateLimited
+                } else {
+                    ExitCode::Rejected
+                };
+            }
+            if cause.is::<Invalid>()
+                || cause.is::<clap::Error>()
+                || cause.is::<DeviceError>()
+                || cause.is::<FormatError>()
+            {
+                return ExitCode::Validation;
+            }
+            if cause.is::<AttachmentError>() {
+                return ExitCode::Attachment;
+            }
+            if let Some(e) = cause.downcast_ref::<NotificationError>() {
+                return match e {
+                    NotificationError::Reqwest(_) | NotificationError::Deserialize(_) => {
+                        ExitCode::Network
+                    }
+                    NotificationError::Attachment(_) => ExitCode::Attachment,
+                    NotificationError::Timestamp(_) => ExitCode::Validation,
+                };
+            }
+            if let Some(e) = cause.downcast_ref::<UserError>() {
+                return match e {
+                    UserError::Reqwest(_) | UserError::Deserialize(_) => ExitCode::Network,
+                    UserError::Api(_) => ExitCode::Rejected,
+                };
+            }
+        }
+        ExitCode::Other
+    }
+}
+
+/// Whether `--output json` is given, for errors found before options are parsed
+pub fn wants_json<S: AsRef<OsStr>>(args: &[S]) -> bool {
+    let args: Vec<_> = args.iter().map(|a| a.as_ref().to_string_lossy()).collect();
+    args.iter().any(|a| a == "--output=json")
+        || args
+            .windows(2)
+            .any(|w| w[0] == "--output" && w[1] == "json")
+}
+
+/// Invalid options or input, exiting with [`ExitCode::Validation`]
+#[derive(Debug)]
+pub struct Invalid(pub String);
+
+impl fmt::Display for Invalid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Invalid {}
+
+/// Response of Pushover API whose status is not 1
+#[derive(Debug)]
+pub struct Rejected(pub Response);
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors = self.0.errors.as_deref().unwrap_or_default();
+        write!(f, "API error: {}", errors.join(", "))
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// JSON object of response, every key is present even if null
+pub fn report(response: &Response, code: ExitCode) -> Value {
+    json!({
+        "status": response.status,
+        "request": response.request,
+        "receipt": response.receipt,
+        "errors": response.errors.as_deref().unwrap_or_default(),
+        "limits": response.limits,
+        "exit_code": code as i32,
+    })
+}
+
+/// JSON object of error, with the response if rejected by Pushover API
+pub fn report_error(error: &anyhow::Error) -> Value {
+    let code = ExitCode::of(error);
+    let rejected = error.chain().find_map(|c| c.downcast_ref::<Rejected>());
+    match rejected {
+        Some(r) => report(&r.0, code),
+        None => json!({
+            "status": 0,
+            "request": null,
+            "receipt": null,
+            "errors": [error.to_string()],
+            "limits": null,
+            "exit_code": code as i32,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use pullover::{AttachmentError, DeviceError, Limits, NotificationError, Response};
+    use serde_json::json;
+
+    use crate::output::{report, report_error, wants_json, ExitCode, Invalid, Rejected};
+    use crate::Opts;
+    use structopt::StructOpt;
+
+    fn rejected(remaining: u64) -> Response {
+        let mut response: Response = serde_json::from_str(
+            r#"{"status":0,"request":"647d2300-702c-4b38-8b2f-d56326ae460b","errors":["application is over its monthly message limit"]}"#,
+        )
+        .unwrap();
+        response.limits = Some(Limits {
+            limit: 10000,
+            remaining,
+            reset: 1393653600,
+        });
+        response
+    }
+
+    fn too_many_requests() -> Response {
+        let mut response = rejected(0);
+        response.limits = None;
+        response.http_status = Some(429);
+        response
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let cases = vec![
+            (anyhow!("other"), ExitCode::Other),
+            (
+                Invalid("message is empty".into()).into(),
+                ExitCode::Validation,
+            ),
+            (
+                anyhow::Error::from(DeviceError::Unknown(vec!["a".into()])).context("devices"),
+                ExitCode::Validation,
+            ),
+            (Rejected(rejected(1)).into(), ExitCode::Rejected),
+            (Rejected(rejected(0)).into(), ExitCode::RateLimited),
+            (Rejected(too_many_requests()).into(), ExitCode::RateLimited),
+            (AttachmentError::Infer.into(), ExitCode::Attachment),
+            (
+                NotificationError::Attachment(AttachmentError::Infer).into(),
+                ExitCode::Attachment,
+            ),
+            (
+                NotificationError::Deserialize(serde_json::from_str::<u8>("").unwrap_err()).into(),
+                ExitCode::Network,
+            ),
+        ];
+        for (error, code) in cases {
+            assert_eq!(code, ExitCode::of(&error), "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn test_clap_error() {
+        let cases = vec![
+            vec!["po2", "--timestamp", "yesterday"],
+            vec!["po2", "--output", "xml"],
+            vec!["po2", "--unknown"],
+        ];
+        for args in cases {
+            let error = anyhow::Error::from(Opts::from_iter_safe(&args).err().unwrap());
+            assert_eq!(ExitCode::Validation, ExitCode::of(&error), "{:?}", args);
+        }
+
+        assert!(wants_json(&["po2", "--output", "json", "--timestamp", "x"]));
+        assert!(wants_json(&["po2", "--output=json"]));
+        assert!(!wants_json(&["po2", "--output", "text"]));
+        assert!(!wants_json(&["po2", "-m", "json"]));
+    }
+
+    #[test]
+    fn test_report() {
+        let response: Response = serde_json::from_str(
+            r#"{"status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            json!({
+                "status": 1,
+                "request": "647d2300-702c-4b38-8b2f-d56326ae460b",
+                "receipt": null,
+                "errors": [],
+                "limits": null,
+                "exit_code": 0,
+            }),
+            report(&response, ExitCode::Success)
+        );
+
+        let value = report_error(&Rejected(rejected(0)).into());
+        assert_eq!(5, value["exit_code"]);
+        assert_eq!(0, value["limits"]["remaining"]);
+        assert_eq!(
+            "application is over its monthly message limit",
+            value["errors"][0]
+        );
+
+        let value = report_error(&Invalid("message is empty".into()).into());
+        assert_eq!(2, value["exit_code"]);
+        assert!(value["request"].is_null());
+        assert_eq!("message is empty", value["errors"][0]);
+    }
+}
@@ -0,0 +1,333 @@
+use std::io::BufRead;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context};
+use futures_util::StreamExt;
+use pullover::open_client::{self, Credentials, Message, OpenClientError};
+use pullover::websocket::{Event, Listener, WebSocketError};
+use pullover::{Priority, DEVICE_NAME_MAX_LENGTH};
+use regex::Regex;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use structopt::StructOpt;
+use tokio::process::Command;
+
+use crate::config::{Config, PriorityValue};
+use crate::secret;
+
+/// Options of `po2 receive`
+#[derive(StructOpt)]
+pub struct ReceiveOpts {
+    /// credentials of the device in JSON, written by `po2 receive login`
+    #[structopt(long, env = "PO2_CREDENTIALS")]
+    credentials: PathBuf,
+    /// acknowledge emergency-priority messages once received
+    #[structopt(long)]
+    acknowledge: bool,
+    #[structopt(subcommand)]
+    command: Option<ReceiveCommand>,
+}
+
+#[derive(StructOpt)]
+enum ReceiveCommand {
+    /// Log in and register this machine as a desktop device <https://pushover.net/api/client#register>
+    Login {
+        /// email of the Pushover account
+        #[structopt(long, env = "PUSHOVER_EMAIL")]
+        email: String,
+        /// password of the Pushover account, read from stdin when omitted
+        #[structopt(long, env = "PUSHOVER_PASSWORD", hide_env_values = true)]
+        password: Option<String>,
+        /// two-factor authentication code
+        #[structopt(long)]
+        twofa: Option<String>,
+        /// name of the device, otherwise derived from hostname
+        #[structopt(long)]
+        name: Option<String>,
+    },
+}
+
+/// Command run for each received message matching all conditions
+///
+/// Fields of message are passed in environment variables e.g. `PO2_TITLE`, `PO2_MESSAGE`.
+#[derive(Debug, Deserialize)]
+pub struct Hook {
+    /// Command run by `sh -c`
+    pub command: String,
+    /// Name of application sending the message
+    pub app: Option<String>,
+    /// Pattern of title
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    pub title: Option<Regex>,
+    /// Priority of message
+    pub priority: Option<PriorityValue>,
+    /// Acknowledge emergency-priority message when the command succeeds
+    #[serde(default)]
+    pub acknowledge: bool,
+}
+
+fn deserialize_regex<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Regex>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| Regex::new(&s).map_err(D::Error::custom))
+        .transpose()
+}
+
+impl Hook {
+    fn matches(&self, message: &Message) -> bool {
+        if matches!(self.app, Some(ref a) if a != &message.app) {
+            return false;
+        }
+        if let Some(ref t) = self.title {
+            if !t.is_match(message.title.as_deref().unwrap_or_default()) {
+                return false;
+            }
+        }
+        !matches!(self.priority, Some(p) if p.0 != message.priority)
+    }
+}
+
+/// Environment variables describing message to hook
+fn envs(message: &Message) -> Vec<(&'static str, String)> {
+    let optional = |v: &Option<String>| v.clone().unwrap_or_default();
+    vec![
+        ("PO2_ID", message.id.to_string()),
+        ("PO2_UMID", message.umid.to_string()),
+        ("PO2_APP", message.app.clone()),
+        ("PO2_TITLE", optional(&message.title)),
+        ("PO2_MESSAGE", message.message.clone()),
+        ("PO2_PRIORITY", message.priority.to_string()),
+        ("PO2_SOUND", optional(&message.sound)),
+        ("PO2_URL", optional(&message.url)),
+        ("PO2_URL_TITLE", optional(&message.url_title)),
+        ("PO2_DATE", message.date.to_string()),
+        ("PO2_RECEIPT", optional(&message.receipt)),
+        ("PO2_HTML", (message.html as u8).to_string()),
+    ]
+}
+
+/// Device names are up to [`DEVICE_NAME_MAX_LENGTH`] characters of letters, numbers, `_` and `-`
+fn device_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .take(DEVICE_NAME_MAX_LENGTH)
+        .collect()
+}
+
+/// Runs `po2 receive`
+pub async fn run(config: &Config, receive: &ReceiveOpts) -> anyhow::Result<()> {
+    if let Some(ref c) = receive.command {
+        return login(receive, c).await;
+    }
+
+    let credentials = Credentials::load(&receive.credentials).with_context(|| {
+        format!(
+            "failed to load credentials {}, run po2 receive login first",
+            receive.credentials.display()
+        )
+    })?;
+    let mut events = Box::pin(Listener::new(credentials.clone()).listen());
+    while let Some(event) = events.next().await {
+        let messages = match event {
+            // download messages received while disconnected
+            Ok(Event::Connected) => match credentials.messages().await {
+                Ok(m) => m,
+                Err(e) => {
+                    eprintln!("failed to download messages: {}", e);
+                    continue;
+                }
+            },
+            Ok(Event::Messages(m)) => m,
+            Ok(_) => continue,
+            Err(e @ WebSocketError::Session) | Err(e @ WebSocketError::LoggedInElsewhere) => {
+                bail!(e)
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+        if let Err(e) = process(config, receive, &credentials, messages).await {
+            eprintln!("failed to process messages: {}", e);
+        }
+    }
+    Ok(())
+}
+
+async fn process(
+    config: &Config,
+    receive: &ReceiveOpts,
+    credentials: &Credentials,
+    mut messages: Vec<Message>,
+) -> Result<(), OpenClientError> {
+    messages.sort_by_key(|m| m.id);
+    for message in &messages {
+        println!("{}", serde_json::to_string(message)?);
+
+        let mut acknowledge = receive.acknowledge;
+        for hook in config.receive.hooks.iter().filter(|h| h.matches(message)) {
+            let status = secret::hide(Command::new("sh").arg("-c").arg(&hook.command))
+                .envs(envs(message))
+                .status()
+                .await;
+            match status {
+                Ok(s) if s.success() => acknowledge |= hook.acknowledge,
+                Ok(s) => eprintln!("hook {} failed: {}", hook.command, s),
+                Err(e) => eprintln!("failed to run hook {}: {}", hook.command, e),
+            }
+        }
+
+        if acknowledge && message.priority == Priority::Emergency && !message.acked {
+            if let Some(ref r) = message.receipt {
+                // deleting messages still has to happen, otherwise hooks run again on reconnection
+                if let Err(e) = credentials.acknowledge(r).await {
+                    eprintln!("failed to acknowledge {}: {}", r, e);
+                }
+            }
+        }
+    }
+    if let Some(m) = messages.last() {
+        credentials.delete_messages(m.id).await?;
+    }
+    Ok(())
+}
+
+async fn login(receive: &ReceiveOpts, command: &ReceiveCommand) -> anyhow::Result<()> {
+    let ReceiveCommand::Login {
+        email,
+        password,
+        twofa,
+        name,
+    } = command;
+    let password = match password {
+        Some(p) => p.clone(),
+        None => {
+            eprint!("password: ");
+            let mut line = String::new();
+            std::io::stdin().lock().read_line(&mut line)?;
+            line.trim_end_matches(&['\r', '\n'][..]).to_string()
+        }
+    };
+    let session = match open_client::login(email, &password, twofa.as_deref()).await {
+        Err(OpenClientError::TwoFactorRequired) => {
+            bail!("two-factor authentication is enabled, run again with --twofa")
+        }
+        r => r?,
+    };
+
+    let name = match name {
+        Some(n) => n.clone(),
+        None => device_name(&hostname::get()?.to_string_lossy()),
+    };
+    let credentials = session.register_device(&name).await?;
+    credentials.save(&receive.credentials)?;
+    eprintln!(
+        "registered device {}, credentials saved to {}",
+        name,
+        receive.credentials.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::mock;
+    use pullover::open_client::{Credentials, Message, OpenClientError};
+    use pullover::{Priority, API_URL_ENV};
+    use structopt::StructOpt;
+
+    use crate::config::Config;
+    use crate::receive::{device_name, envs, process, ReceiveOpts};
+
+    fn message() -> Message {
+        serde_json::from_str(
+            r#"{"id":2,"umid":12,"title":"Backup failed","message":"disk full","app":"po2","aid":111,"icon":"po2","date":1629451260,"priority":2,"receipt":"receipt"}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_matches() {
+        let config: Config = toml::from_str(
+            r#"
+            [[receive.hooks]]
+            command = "true"
+            app = "po2"
+            title = "^Backup"
+            priority = 2
+
+            [[receive.hooks]]
+            command = "true"
+            app = "other"
+
+            [[receive.hooks]]
+            command = "true"
+            title = "succeeded$"
+
+            [[receive.hooks]]
+            command = "true"
+            priority = 0
+            "#,
+        )
+        .unwrap();
+        let hooks = config.receive.hooks;
+        let message = message();
+        assert_eq!(Priority::Emergency, message.priority);
+        assert!(hooks[0].matches(&message));
+        assert!(!hooks[1].matches(&message));
+        assert!(!hooks[2].matches(&message));
+        assert!(!hooks[3].matches(&message));
+
+        assert!(
+            toml::from_str::<Config>("[[receive.hooks]]\ncommand = \"true\"\ntitle = \"(\"")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_envs() {
+        let envs = envs(&message());
+        let get = |k: &str| envs.iter().find(|(n, _)| *n == k).map(|(_, v)| v.as_str());
+        assert_eq!(Some("Backup failed"), get("PO2_TITLE"));
+        assert_eq!(Some("2"), get("PO2_PRIORITY"));
+        assert_eq!(Some("receipt"), get("PO2_RECEIPT"));
+        assert_eq!(Some(""), get("PO2_URL"));
+    }
+
+    #[tokio::test]
+    async fn test_process() -> Result<(), OpenClientError> {
+        std::env::set_var(API_URL_ENV, mockito::server_url());
+        let acknowledge = mock("POST", "/1/receipts/receipt/acknowledge.json")
+            .with_status(500)
+            .create();
+        let delete = mock("POST", "/1/devices/device/update_highest_message.json")
+            .match_body("secret=secret&message=2")
+            .with_status(200)
+            .with_body(r#"{"status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+
+        let receive = ReceiveOpts::from_iter(&["receive", "--credentials", "c", "--acknowledge"]);
+        let credentials = Credentials {
+            user_id: "user".into(),
+            secret: "secret".into(),
+            device_id: "device".into(),
+        };
+        process(&Config::default(), &receive, &credentials, vec![message()]).await?;
+        acknowledge.assert();
+        delete.assert();
+        Ok(())
+    }
+
+    #[test]
+    fn test_device_name() {
+        assert_eq!("build-example-com", device_name("build.example.com"));
+        assert_eq!(25, device_name(&"a".repeat(30)).len());
+    }
+}
@@ -0,0 +1,179 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Stdio;
+
+use anyhow::Context;
+use tokio::process::Command;
+
+use crate::output::Invalid;
+
+/// Directory of systemd credentials e.g. of `LoadCredential=pushover-token:/etc/po2/token`
+pub const CREDENTIALS_DIRECTORY: &str = "CREDENTIALS_DIRECTORY";
+
+/// Service of entries in Secret Service keyring, whose users are `token` and `user`
+#[cfg(feature = "keyring")]
+pub const KEYRING_SERVICE: &str = "po2";
+
+/// Environment variables of secrets or where to find them, see [`hide`]
+pub const ENV_VARS: &[&str] = &[
+    "PUSHOVER_TOKEN",
+    "PUSHOVER_TOKEN_FILE",
+    "PUSHOVER_USER",
+    "PUSHOVER_USER_FILE",
+];
+
+/// Keeps [`ENV_VARS`] away from a child process e.g. of `po2 exec` or a receive hook
+pub fn hide(command: &mut Command) -> &mut Command {
+    for name in ENV_VARS {
+        command.env_remove(name);
+    }
+    command
+}
+
+/// Where a secret may come from, looked up in order of fields
+#[derive(Debug)]
+pub struct Source<'a> {
+    /// File given by e.g. `--token-file`
+    pub file: Option<&'a Path>,
+    /// Name of systemd credential under [`CREDENTIALS_DIRECTORY`] e.g. `pushover-token`
+    pub credential: &'a str,
+    /// Shell command printing the secret e.g. `token_cmd = "pass show pushover/token"`
+    pub command: Option<&'a str>,
+    /// User of entry in Secret Service keyring e.g. `token`
+    #[cfg_attr(not(feature = "keyring"), allow(dead_code))]
+    pub keyring: &'a str,
+}
+
+impl Source<'_> {
+    /// Reads secret from the first source found, or nothing
+    pub async fn read(&self) -> anyhow::Result<Option<String>> {
+        if let Some(p) = self.file {
+            return read_file(p).map(Some);
+        }
+        if let Some(dir) = env::var_os(CREDENTIALS_DIRECTORY) {
+            let path = Path::new(&dir).join(self.credential);
+            if path.is_file() {
+                return read_file(&path).map(Some);
+            }
+        }
+        if let Some(c) = self.command {
+            return run_command(c).await.map(Some);
+        }
+        #[cfg(feature = "keyring")]
+        if let Some(s) = read_keyring(self.keyring)? {
+            return Ok(Some(s));
+        }
+        Ok(None)
+    }
+}
+
+/// Reads secret from file, surrounding whitespace e.g. a trailing newline is ignored
+fn read_file(path: &Path) -> anyhow::Result<String> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("failed to read secret from {}", path.display()))?;
+    non_empty(content, || path.display().to_string())
+}
+
+/// Runs shell command and reads secret from its stdout, stderr is passed through
+async fn run_command(command: &str) -> anyhow::Result<String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .await
+        .with_context(|| format!("failed to run {}", command))?;
+    if !output.status.success() {
+        return Err(Invalid(format!("{} failed: {}", command, output.status)).into());
+    }
+    let stdout = String::from_utf8(output.stdout)
+        .with_context(|| format!("output of {} is not UTF-8", command))?;
+    non_empty(stdout, || command.to_string())
+}
+
+/// Reads secret from Secret Service keyring, nothing if there is no such entry or no keyring at all
+#[cfg(feature = "keyring")]
+fn read_keyring(user: &str) -> anyhow::Result<Option<String>> {
+    let entry = keyring::Entry::new(KEYRING_SERVICE, user)?;
+    match entry.get_password() {
+        Ok(s) => non_empty(s, || format!("keyring entry {}", user)).map(Some),
+        Err(keyring::Error::NoEntry)
+        | Err(keyring::Error::PlatformFailure(_))
+        | Err(keyring::Error::NoStorageAccess(_)) => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("failed to read keyring entry {}", user)),
+    }
+}
+
+fn non_empty<F: FnOnce() -> String>(secret: String, source: F) -> anyhow::Result<String> {
+    let secret = secret.trim();
+    if secret.is_empty() {
+        return Err(Invalid(format!("secret from {} is empty", source())).into());
+    }
+    Ok(secret.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use tokio::process::Command;
+
+    use crate::secret::{hide, Source, CREDENTIALS_DIRECTORY};
+
+    fn source<'a>(command: Option<&'a str>) -> Source<'a> {
+        Source {
+            file: None,
+            credential: "pushover-token",
+            command,
+            keyring: "token",
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read() -> anyhow::Result<()> {
+        let dir = env::temp_dir().join(format!("po2-secret-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let file = dir.join("token");
+        fs::write(&file, "from-file\n")?;
+        fs::write(dir.join("pushover-token"), "from-credential\n")?;
+        let empty = dir.join("empty");
+        fs::write(&empty, "\n")?;
+
+        let command = Some("echo from-command");
+        let mut s = source(command);
+        s.file = Some(&file);
+        assert_eq!(Some("from-file".to_string()), s.read().await?);
+        s.file = Some(&empty);
+        assert!(s.read().await.is_err());
+
+        assert_eq!(
+            Some("from-command".to_string()),
+            source(command).read().await?
+        );
+        assert!(source(Some("exit 1")).read().await.is_err());
+
+        env::set_var(CREDENTIALS_DIRECTORY, &dir);
+        let credential = source(command).read().await;
+        env::remove_var(CREDENTIALS_DIRECTORY);
+        assert_eq!(Some("from-credential".to_string()), credential?);
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_hide() -> anyhow::Result<()> {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg("echo ${PUSHOVER_TOKEN-unset} ${PUSHOVER_USER_FILE-unset}")
+            .env("PUSHOVER_TOKEN", "secret-token")
+            .env("PUSHOVER_USER_FILE", "/run/secrets/user");
+        let output = hide(&mut command).output().await?;
+        assert_eq!("unset unset\n", String::from_utf8(output.stdout)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,657 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use hyper::body::{Bytes, HttpBody};
+use hyper::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use prometheus::{Encoder, Registry, TextEncoder};
+use pullover::{receipt, Attachment, DeviceSet, Format, Notification, Priority, Sound};
+use serde::Serialize;
+use serde_json::Value;
+use structopt::StructOpt;
+
+use crate::alertmanager::{self, Payload};
+use crate::config::{AlertmanagerConfig, Client, Config};
+use crate::timestamp::Timestamp;
+use crate::webhook::Route;
+use crate::Opts;
+
+/// Larger bodies are rejected, Pushover itself limits attachments to 2.5 MB
+const MAX_BODY_SIZE: usize = 5 * 1024 * 1024;
+
+/// Options of `po2 serve`
+#[derive(StructOpt)]
+pub struct ServeOpts {
+    /// address to listen on
+    #[structopt(long, default_value = "127.0.0.1:8000")]
+    listen: SocketAddr,
+}
+
+/// Shared by all connections of the relay
+struct State {
+    token: String,
+    user: String,
+    clients: Vec<Client>,
+    alertmanager: AlertmanagerConfig,
+    routes: Vec<Route>,
+    registry: Registry,
+}
+
+impl State {
+    /// Finds webhook route by path e.g. `/hooks/gitea`
+    fn route(&self, path: &str) -> Option<&Route> {
+        let name = path.strip_prefix("/hooks/")?;
+        self.routes.iter().find(|r| r.name == name)
+    }
+}
+
+/// Error response in the same shape as Pushover API
+#[derive(Debug, Serialize)]
+struct Rejection {
+    #[serde(skip)]
+    code: StatusCode,
+    status: u8,
+    errors: Vec<String>,
+}
+
+impl Rejection {
+    fn new<T: ToString>(code: StatusCode, error: T) -> Self {
+        Self {
+            code,
+            status: 0,
+            errors: vec![error.to_string()],
+        }
+    }
+
+    fn bad_request<T: ToString>(error: T) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, error)
+    }
+}
+
+/// Message posted to the relay, fields mirror [`pullover::Request`]
+#[derive(Debug, Default)]
+struct Message {
+    fields: HashMap<String, String>,
+    attachment: Option<Attachment>,
+}
+
+/// Runs HTTP relay which forwards notifications to Pushover with token kept on the server side
+///
+/// Clients post JSON, URL-encoded or multipart form to `/1/messages.json`, just like the Pushover API.
+/// Alertmanager posts its webhook to `/alertmanager`, others post to `/hooks/<name>` of configured routes.
+/// Metrics of notifications are exposed at `/metrics` in Prometheus text format without authentication.
+pub async fn run(opts: &Opts, config: Config, serve: &ServeOpts) -> anyhow::Result<()> {
+    let registry = Registry::new();
+    pullover::metrics::register(&registry)?;
+    let state = Arc::new(State {
+        token: opts.token()?.to_string(),
+        user: opts.user()?.to_string(),
+        clients: config.serve.clients,
+        alertmanager: config.alertmanager,
+        routes: config.serve.routes,
+        registry,
+    });
+    if state.clients.is_empty() {
+        eprintln!(
+            "po2: no clients configured, anyone reaching {} can send notifications",
+            serve.listen
+        );
+    }
+
+    let make_service = make_service_fn(move |conn: &AddrStream| {
+        let state = Arc::clone(&state);
+        let remote = conn.remote_addr();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                handle(Arc::clone(&state), remote, req)
+            }))
+        }
+    });
+    eprintln!("po2: listening on {}", serve.listen);
+    Server::try_bind(&serve.listen)?.serve(make_service).await?;
+    Ok(())
+}
+
+async fn handle(
+    state: Arc<State>,
+    remote: SocketAddr,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let started = Instant::now();
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    if method == Method::GET && path == "/metrics" {
+        return Ok(metrics(&state));
+    }
+
+    let (client, result) = match authenticate(&state, &req) {
+        Ok(client) => (client, route(&state, req).await),
+        Err(r) => ("-".to_string(), Err(r)),
+    };
+    let (code, body, request) = match result {
+        Ok((code, res)) => {
+            let body = serde_json::to_string(&res).unwrap_or_default();
+            (code, body, res.request)
+        }
+        Err(r) => {
+            let body = serde_json::to_string(&r).unwrap_or_default();
+            (r.code, body, "-".to_string())
+        }
+    };
+
+    eprintln!(
+        "po2: {} {} {} {} {} request={} {}ms",
+        remote,
+        client,
+        method,
+        path,
+        code.as_u16(),
+        request,
+        started.elapsed().as_millis()
+    );
+
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = code;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+    Ok(response)
+}
+
+/// Responds with metrics in Prometheus text format
+fn metrics(state: &State) -> Response<Body> {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    let mut response = match encoder.encode(&state.registry.gather(), &mut buffer) {
+        Ok(_) => Response::new(Body::from(buffer)),
+        Err(e) => {
+            let mut response = Response::new(Body::from(e.to_string()));
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            response
+        }
+    };
+    if let Ok(v) = encoder.format_type().parse() {
+        response.headers_mut().insert(CONTENT_TYPE, v);
+    }
+    response
+}
+
+/// Returns name of client with matching bearer token
+///
+/// Webhook routes with secret are authenticated by signature instead.
+fn authenticate(state: &State, req: &Request<Body>) -> Result<String, Rejection> {
+    if let Some(route) = state.route(req.uri().path()) {
+        if route.secret.is_some() {
+            return Ok(format!("hook:{}", route.name));
+        }
+    }
+    if state.clients.is_empty() {
+        return Ok("anonymous".to_string());
+    }
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or_else(|| Rejection::new(StatusCode::UNAUTHORIZED, "bearer token is required"))?;
+    state
+        .clients
+        .iter()
+        .find(|c| constant_time_eq(c.token.as_bytes(), token.trim().as_bytes()))
+        .map(|c| c.name.clone())
+        .ok_or_else(|| Rejection::new(StatusCode::UNAUTHORIZED, "bearer token is invalid"))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn route(
+    state: &State,
+    req: Request<Body>,
+) -> Result<(StatusCode, pullover::Response), Rejection> {
+    match (req.method(), req.uri().path()) {
+        (&Method::POST, "/1/messages.json") => relay(state, req).await,
+        (&Method::POST, "/alertmanager") => receive_alerts(state, req).await,
+        (&Method::POST, path) if state.route(path).is_some() => receive_hook(state, req).await,
+        _ => Err(Rejection::new(StatusCode::NOT_FOUND, "not found")),
+    }
+}
+
+async fn relay(
+    state: &State,
+    req: Request<Body>,
+) -> Result<(StatusCode, pullover::Response), Rejection> {
+    let message = parse(req).await?;
+    forward(build_notification(state, &message)?).await
+}
+
+/// Sends alert group from Alertmanager, cancelling emergency-priority notification once resolved
+async fn receive_alerts(
+    state: &State,
+    mut req: Request<Body>,
+) -> Result<(StatusCode, pullover::Response), Rejection> {
+    let body = read_body(&mut req).await?;
+    let payload: Payload = serde_json::from_slice(&body).map_err(Rejection::bad_request)?;
+    let config = &state.alertmanager;
+    let group = alertmanager::translate(config, &payload).map_err(Rejection::bad_request)?;
+
+    if group.resolved {
+        if let Err(e) = receipt::cancel_by_tag(&state.token, &group.tag).await {
+            eprintln!("po2: failed to cancel {}: {}", group.tag, e);
+        }
+    }
+
+    let user = config.user.as_ref().unwrap_or(&state.user);
+    let mut notification = Notification::new(&state.token, user, &group.message);
+    let request = &mut notification.request;
+    request.title = Some(group.title.as_str().into());
+    request.url = group.url.as_ref().map(|u| u.into());
+    request.priority = Some(group.priority);
+    if group.priority == Priority::Emergency {
+        request.retry = Some(config.retry);
+        request.expire = Some(config.expire);
+        request.tags = Some(group.tag.as_str().into());
+    }
+    forward(notification).await
+}
+
+/// Sends notification rendered from JSON webhook by route
+async fn receive_hook(
+    state: &State,
+    mut req: Request<Body>,
+) -> Result<(StatusCode, pullover::Response), Rejection> {
+    let route = state
+        .route(req.uri().path())
+        .ok_or_else(|| Rejection::new(StatusCode::NOT_FOUND, "not found"))?;
+    let signature = req
+        .headers()
+        .get(route.signature_header.as_str())
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    let body = read_body(&mut req).await?;
+    if !route.verify(signature.as_deref(), &body) {
+        return Err(Rejection::new(
+            StatusCode::UNAUTHORIZED,
+            "signature is invalid",
+        ));
+    }
+
+    let body: Value = serde_json::from_slice(&body).map_err(Rejection::bad_request)?;
+    let message = route.render(&body).map_err(Rejection::bad_request)?;
+    let user = route.user.as_ref().unwrap_or(&state.user);
+    let mut notification = Notification::new(&state.token, user, &message.message);
+    let request = &mut notification.request;
+    request.title = message.title.as_ref().map(|t| t.into());
+    request.url = message.url.as_ref().map(|u| u.into());
+    request.url_title = message.url_title.as_ref().map(|t| t.into());
+    request.priority = message.priority;
+    request.sound = message.sound;
+    if message.priority == Some(Priority::Emergency) {
+        request.retry = Some(route.retry);
+        request.expire = Some(route.expire);
+    }
+    forward(notification).await
+}
+
+/// Sends notification to Pushover, responding with status code by its status, 429 is passed through
+async fn forward(
+    notification: Notification<'_>,
+) -> Result<(StatusCode, pullover::Response), Rejection> {
+    let res = notification
+        .send()
+        .await
+        .map_err(|e| Rejection::new(StatusCode::BAD_GATEWAY, e))?;
+    let code = if res.status == 1 {
+        StatusCode::OK
+    } else if res.is_rate_limited() {
+        StatusCode::TOO_MANY_REQUESTS
+    } else {
+        StatusCode::BAD_REQUEST
+    };
+    Ok((code, res))
+}
+
+/// Reads whole body, rejecting it when larger than [`MAX_BODY_SIZE`]
+async fn read_body(req: &mut Request<Body>) -> Result<Bytes, Rejection> {
+    let too_large = || Rejection::new(StatusCode::PAYLOAD_TOO_LARGE, "request is too large");
+    let length = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if matches!(length, Some(l) if l > MAX_BODY_SIZE) {
+        return Err(too_large());
+    }
+    let mut buffer = Vec::new();
+    while let Some(chunk) = req.body_mut().data().await {
+        let chunk = chunk.map_err(Rejection::bad_request)?;
+        if buffer.len() + chunk.len() > MAX_BODY_SIZE {
+            return Err(too_large());
+        }
+        buffer.extend_from_slice(&chunk);
+    }
+    Ok(buffer.into())
+}
+
+/// Parses JSON, multipart or URL-encoded form into [`Message`]
+async fn parse(mut req: Request<Body>) -> Result<Message, Rejection> {
+    let content_type = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let body = read_body(&mut req).await?;
+
+    let mut message = if content_type.starts_with("application/json") {
+        parse_json(&body)?
+    } else if content_type.starts_with("multipart/form-data") {
+        parse_multipart(&content_type, body).await?
+    } else {
+        Message {
+            fields: url::form_urlencoded::parse(&body).into_owned().collect(),
+            ..Default::default()
+        }
+    };
+
+    // like Pushover API, attachment may also be sent as base64 string
+    if let Some(encoded) = message.fields.remove("attachment_base64") {
+        let content = base64::decode(encoded.trim()).map_err(Rejection::bad_request)?;
+        let mime_type = message
+            .fields
+            .remove("attachment_type")
+            .ok_or_else(|| Rejection::bad_request("attachment_type is required"))?;
+        let filename = message
+            .fields
+            .remove("attachment_name")
+            .unwrap_or_else(|| "attachment".to_string());
+        message.attachment = Some(Attachment::new(&filename, &mime_type, &content));
+    }
+
+    Ok(message)
+}
+
+fn parse_json(body: &[u8]) -> Result<Message, Rejection> {
+    let object: serde_json::Map<String, Value> =
+        serde_json::from_slice(body).map_err(Rejection::bad_request)?;
+    let mut fields = HashMap::new();
+    for (name, value) in object {
+        let value = match value {
+            Value::Null => continue,
+            Value::Bool(b) => if b { "1" } else { "0" }.to_string(),
+            Value::Number(n) => n.to_string(),
+            Value::String(s) => s,
+            _ => return Err(Rejection::bad_request(format!("{} is invalid", name))),
+        };
+        fields.insert(name, value);
+    }
+    Ok(Message {
+        fields,
+        ..Default::default()
+    })
+}
+
+async fn parse_multipart(content_type: &str, body: Bytes) -> Result<Message, Rejection> {
+    let boundary = multer::parse_boundary(content_type).map_err(Rejection::bad_request)?;
+    let stream = futures_util::stream::once(async move { Ok::<_, Infallible>(body) });
+    let mut multipart = multer::Multipart::new(stream, boundary);
+
+    let mut message = Message::default();
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(Rejection::bad_request)?
+    {
+        let name = field.name().unwrap_or_default().to_string();
+        if name == "attachment" {
+            let filename = field.file_name().unwrap_or("attachment").to_string();
+            let mime_type = field
+                .content_type()
+                .map_or("application/octet-stream".to_string(), |m| m.to_string());
+            let content = field.bytes().await.map_err(Rejection::bad_request)?;
+            message.attachment = Some(Attachment::new(&filename, &mime_type, &content));
+        } else {
+            let value = field.text().await.map_err(Rejection::bad_request)?;
+            message.fields.insert(name, value);
+        }
+    }
+    Ok(message)
+}
+
+fn parse_field<T: FromStr>(message: &Message, name: &str) -> Result<Option<T>, Rejection> {
+    match message.fields.get(name) {
+        Some(v) => T::from_str(v)
+            .map(Some)
+            .map_err(|_| Rejection::bad_request(format!("{} is invalid", name))),
+        None => Ok(None),
+    }
+}
+
+/// Creates [`Notification`] from [`Message`], with token of the server
+fn build_notification<'a>(
+    state: &'a State,
+    message: &'a Message,
+) -> Result<Notification<'a>, Rejection> {
+    let fields = &message.fields;
+    let text = fields
+        .get("message")
+        .ok_or_else(|| Rejection::bad_request("message cannot be blank"))?;
+    let user = fields.get("user").unwrap_or(&state.user);
+
+    let mut notification = Notification::new(&state.token, user, text);
+    let request = &mut notification.request;
+    request.device = parse_field::<DeviceSet>(message, "device")?;
+    request.title = fields.get("title").map(|t| t.into());
+    let html = matches!(parse_field::<u8>(message, "html")?, Some(v) if v != 0);
+    let monospace = matches!(parse_field::<u8>(message, "monospace")?, Some(v) if v != 0);
+    request.format = match Format::from_flags(html, monospace) {
+        Ok(Format::Plain) => None,
+        Ok(f) => Some(f),
+        Err(e) => return Err(Rejection::bad_request(e.to_string())),
+    };
+    request.timestamp = parse_field::<Timestamp>(message, "timestamp")?.map(|t| t.0);
+    request.priority = parse_field::<Priority>(message, "priority")?;
+    request.retry = parse_field::<u32>(message, "retry")?;
+    request.expire = parse_field::<u32>(message, "expire")?;
+    request.tags = fields.get("tags").map(|t| t.into());
+    request.url = fields.get("url").map(|u| u.into());
+    request.url_title = fields.get("url_title").map(|t| t.into());
+    request.sound = parse_field::<Sound>(message, "sound")?;
+
+    if let Some(ref a) = message.attachment {
+        notification.attach(a);
+    }
+    Ok(notification)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+    use hyper::{Body, Request, StatusCode};
+    use mockito::{mock, Matcher};
+    use pullover::API_URL_ENV;
+
+    use crate::config::Client;
+    use crate::serve::{authenticate, build_notification, metrics, parse, route, State};
+
+    fn state() -> State {
+        State {
+            token: "token".into(),
+            user: "user".into(),
+            clients: vec![Client {
+                name: "ci".into(),
+                token: "secret".into(),
+            }],
+            alertmanager: Default::default(),
+            routes: vec![
+                toml::from_str(
+                    r#"
+                    name = "signed"
+                    secret = "secret"
+                    message = "hello"
+                    "#,
+                )
+                .unwrap(),
+                toml::from_str(
+                    r#"
+                    name = "emergency"
+                    message = "{message}"
+                    priority = "2"
+                    fields = { message = "$.message" }
+                    "#,
+                )
+                .unwrap(),
+            ],
+            registry: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let state = state();
+        let request = |authorization: Option<&str>| {
+            let mut req = Request::post("/1/messages.json");
+            if let Some(a) = authorization {
+                req = req.header(AUTHORIZATION, a);
+            }
+            req.body(Body::empty()).unwrap()
+        };
+        assert!(authenticate(&state, &request(None)).is_err());
+        assert!(authenticate(&state, &request(Some("Bearer wrong"))).is_err());
+        assert_eq!(
+            "ci",
+            authenticate(&state, &request(Some("Bearer secret"))).unwrap()
+        );
+
+        let req = Request::post("/hooks/signed").body(Body::empty()).unwrap();
+        assert_eq!("hook:signed", authenticate(&state, &req).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_receive_hook_emergency() {
+        std::env::set_var(API_URL_ENV, mockito::server_url());
+        let _m = mock("POST", "/1/messages.json")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::Regex("server on fire".into()),
+                Matcher::Regex("name=\"priority\"\r\n\r\n2\r\n".into()),
+                Matcher::Regex("name=\"retry\"\r\n\r\n60\r\n".into()),
+                Matcher::Regex("name=\"expire\"\r\n\r\n3600\r\n".into()),
+            ]))
+            .with_status(200)
+            .with_body(
+                r#"{"status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b","receipt":"r"}"#,
+            )
+            .create();
+        let req = Request::post("/hooks/emergency")
+            .body(Body::from(r#"{"message":"server on fire"}"#))
+            .unwrap();
+        let (code, res) = route(&state(), req).await.unwrap();
+        assert_eq!(StatusCode::OK, code);
+        assert_eq!(Some("r".to_string()), res.receipt);
+    }
+
+    #[tokio::test]
+    async fn test_relay_rate_limited() {
+        std::env::set_var(API_URL_ENV, mockito::server_url());
+        let _m = mock("POST", "/1/messages.json")
+            .match_body(Matcher::Regex("over the limit".into()))
+            .with_status(429)
+            .with_body(r#"{"status":0,"errors":["application is over its monthly message limit"],"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        let req = Request::post("/1/messages.json")
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from("message=over+the+limit"))
+            .unwrap();
+        let (code, res) = route(&state(), req).await.unwrap();
+        assert_eq!(StatusCode::TOO_MANY_REQUESTS, code);
+        assert_eq!(0, res.status);
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let state = state();
+        pullover::metrics::register(&state.registry).unwrap();
+        let response = metrics(&state);
+        assert_eq!(
+            "text/plain; version=0.0.4",
+            response.headers()[CONTENT_TYPE].to_str().unwrap()
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        assert!(
+            text.contains("# TYPE pullover_app_remaining gauge"),
+            "{}",
+            text
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_json() {
+        let req = Request::post("/1/messages.json")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                r#"{"message":"hello","priority":2,"retry":30,"expire":600,"tags":"db","html":true,"timestamp":"2021-08-20T09:20:00Z","attachment_base64":"aGVsbG8=","attachment_type":"text/plain"}"#,
+            ))
+            .unwrap();
+        let message = parse(req).await.unwrap();
+        assert_eq!("2", message.fields["priority"]);
+        assert_eq!("1", message.fields["html"]);
+        assert!(message.attachment.is_some());
+
+        let state = state();
+        let notification = build_notification(&state, &message).unwrap();
+        assert_eq!(
+            Some(pullover::Priority::Emergency),
+            notification.request.priority
+        );
+        assert_eq!(Some(30), notification.request.retry);
+        assert_eq!(Some(600), notification.request.expire);
+        assert_eq!(Some("db"), notification.request.tags.as_deref());
+        assert_eq!(Some(pullover::Format::HTML), notification.request.format);
+        assert_eq!(
+            Some(UNIX_EPOCH + Duration::from_secs(1_629_451_200)),
+            notification.request.timestamp
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_form() {
+        let req = Request::post("/1/messages.json")
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from("message=hello+world&sound=bike&priority=9"))
+            .unwrap();
+        let message = parse(req).await.unwrap();
+        assert_eq!("hello world", message.fields["message"]);
+
+        let state = state();
+        assert!(build_notification(&state, &message).is_err());
+
+        let req = Request::post("/1/messages.json")
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from("message=hello&html=1&monospace=1"))
+            .unwrap();
+        let message = parse(req).await.unwrap();
+        assert!(build_notification(&state, &message).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_parse_multipart() {
+        let body = "--b\r\nContent-Disposition: form-data; name=\"message\"\r\n\r\nhello\r\n--b\r\nContent-Disposition: form-data; name=\"attachment\"; filename=\"a.png\"\r\nContent-Type: image/png\r\n\r\npng\r\n--b--\r\n";
+        let req = Request::post("/1/messages.json")
+            .header(CONTENT_TYPE, "multipart/form-data; boundary=b")
+            .body(Body::from(body))
+            .unwrap();
+        let message = parse(req).await.unwrap();
+        assert_eq!("hello", message.fields["message"]);
+        assert!(message.attachment.is_some());
+    }
+}
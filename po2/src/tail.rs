@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use structopt::StructOpt;
+
+use crate::template::Rendered;
+use crate::Opts;
+
+/// Matched lines kept for a batch, older lines are dropped
+const MAX_BATCH_LINES: usize = 100;
+
+/// Options of `po2 tail`
+#[derive(StructOpt)]
+pub struct TailOpts {
+    /// log file to follow
+    file: PathBuf,
+    /// regular expression matched against every new line
+    #[structopt(long = "match")]
+    pattern: Regex,
+    /// seconds to wait after a notification before sending the next one, matches in between are sent as a batch
+    #[structopt(long, default_value = "60")]
+    cooldown: u64,
+    /// milliseconds between checks of the file
+    #[structopt(long, default_value = "500")]
+    interval: u64,
+    /// read the file from the beginning instead of from the end
+    #[structopt(long)]
+    from_start: bool,
+}
+
+/// Follows a file by path like `tail -F`, surviving rotation and truncation
+struct Follower {
+    path: PathBuf,
+    file: Option<File>,
+    id: Option<u64>,
+    position: u64,
+    partial: Vec<u8>,
+}
+
+impl Follower {
+    fn new(path: &Path, from_start: bool) -> io::Result<Self> {
+        let mut follower = Self {
+            path: path.to_path_buf(),
+            file: None,
+            id: None,
+            position: 0,
+            partial: Vec::new(),
+        };
+        follower.open()?;
+        if !from_start {
+            if let Some(ref mut f) = follower.file {
+                follower.position = f.seek(SeekFrom::End(0))?;
+            }
+        }
+        Ok(follower)
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        let file = File::open(&self.path)?;
+        self.id = file_id(&file.metadata()?);
+        self.file = Some(file);
+        self.position = 0;
+        self.partial.clear();
+        Ok(())
+    }
+
+    /// Returns complete lines appended since last poll
+    fn poll(&mut self) -> io::Result<Vec<String>> {
+        let mut lines = Vec::new();
+        let current = match fs::metadata(&self.path) {
+            Ok(m) => Some(m),
+            // file is being rotated, keep reading the old one for now
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+
+        if self.file.is_none() {
+            if current.is_none() {
+                return Ok(lines);
+            }
+            self.open()?;
+        }
+
+        if let Some(ref m) = current {
+            if file_id(m) != self.id {
+                // rotated, drain what is left in the old file before switching
+                self.read(&mut lines)?;
+                self.open()?;
+            } else if m.len() < self.position {
+                // truncated in place, start over
+                self.position = 0;
+                self.partial.clear();
+            }
+        }
+
+        self.read(&mut lines)?;
+        Ok(lines)
+    }
+
+    fn read(&mut self, lines: &mut Vec<String>) -> io::Result<()> {
+        let file = match self.file {
+            Some(ref mut f) => f,
+            None => return Ok(()),
+        };
+        file.seek(SeekFrom::Start(self.position))?;
+        let mut buffer = Vec::new();
+        self.position += file.read_to_end(&mut buffer)? as u64;
+        for b in buffer {
+            if b == b'\n' {
+                let line = String::from_utf8_lossy(&self.partial);
+                lines.push(line.trim_end_matches('\r').to_string());
+                self.partial.clear();
+            } else {
+                self.partial.push(b);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> Option<u64> {
+    None
+}
+
+/// Matched line with its capture groups, indexed by both number and name
+struct Match {
+    line: String,
+    captures: HashMap<String, String>,
+}
+
+impl Match {
+    fn new(pattern: &Regex, line: String) -> Option<Self> {
+        let c = pattern.captures(&line)?;
+        let mut captures = HashMap::new();
+        for (i, m) in c.iter().enumerate() {
+            if let Some(m) = m {
+                captures.insert(i.to_string(), m.as_str().to_string());
+            }
+        }
+        for name in pattern.capture_names().flatten() {
+            if let Some(m) = c.name(name) {
+                captures.insert(name.to_string(), m.as_str().to_string());
+            }
+        }
+        Some(Self { line, captures })
+    }
+}
+
+/// Follows file and sends notifications for matched lines
+///
+/// Message, title, URL and URL title are templates with capture groups of the latest match
+/// e.g. `{1}` or `{name}`, along with `{line}`, `{lines}`, `{count}` and `{file}`.
+pub async fn run(opts: &Opts, tail: &TailOpts) -> anyhow::Result<()> {
+    let mut follower = Follower::new(&tail.file, tail.from_start)?;
+    let cooldown = Duration::from_secs(tail.cooldown);
+    let interval = Duration::from_millis(tail.interval.max(10));
+
+    let mut batch: Vec<Match> = Vec::new();
+    let mut count = 0;
+    let mut last_sent: Option<Instant> = None;
+    loop {
+        for line in follower.poll()? {
+            if let Some(m) = Match::new(&tail.pattern, line) {
+                count += 1;
+                batch.push(m);
+                if batch.len() > MAX_BATCH_LINES {
+                    batch.remove(0);
+                }
+            }
+        }
+
+        let cooled = !matches!(last_sent, Some(t) if t.elapsed() < cooldown);
+        if cooled && !batch.is_empty() {
+            if let Err(e) = notify(opts, &tail.file, &batch, count).await {
+                eprintln!("po2: failed to send notification: {:#}", e);
+            }
+            batch.clear();
+            count = 0;
+            last_sent = Some(Instant::now());
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+async fn notify(opts: &Opts, file: &Path, batch: &[Match], count: usize) -> anyhow::Result<()> {
+    let latest = match batch.last() {
+        Some(m) => m,
+        None => return Ok(()),
+    };
+    let lines: Vec<&str> = batch.iter().map(|m| m.line.as_str()).collect();
+    let lookup = |name: &str| match name {
+        "line" => Some(latest.line.clone()),
+        "lines" => Some(lines.join("\n")),
+        "count" => Some(count.to_string()),
+        "file" => Some(file.display().to_string()),
+        _ => latest.captures.get(name).cloned(),
+    };
+    let rendered = Rendered::new(opts, "{lines}", "{file}", lookup).truncate(opts);
+    opts.send(rendered.notification(opts)?).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    use regex::Regex;
+
+    use crate::tail::{Follower, Match};
+
+    #[test]
+    fn test_match() {
+        let pattern = Regex::new(r"(?P<level>ERROR|WARN) (\w+)").unwrap();
+        assert!(Match::new(&pattern, "INFO fine".into()).is_none());
+
+        let m = Match::new(&pattern, "12:00 ERROR disk full".into()).unwrap();
+        assert_eq!("ERROR disk", m.captures["0"]);
+        assert_eq!("ERROR", m.captures["1"]);
+        assert_eq!("disk", m.captures["2"]);
+        assert_eq!("ERROR", m.captures["level"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follower() -> std::io::Result<()> {
+        let dir = std::env::temp_dir().join(format!("po2-tail-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("app.log");
+        fs::write(&path, "old\n")?;
+
+        let mut follower = Follower::new(&path, false)?;
+        assert!(follower.poll()?.is_empty());
+
+        let mut f = OpenOptions::new().append(true).open(&path)?;
+        write!(f, "one\ntw")?;
+        assert_eq!(vec!["one"], follower.poll()?);
+        writeln!(f, "o")?;
+        assert_eq!(vec!["two"], follower.poll()?);
+
+        // truncation
+        fs::write(&path, "three\n")?;
+        assert_eq!(vec!["three"], follower.poll()?);
+
+        // rotation
+        let mut f = OpenOptions::new().append(true).open(&path)?;
+        writeln!(f, "four")?;
+        fs::rename(&path, dir.join("app.log.1"))?;
+        fs::write(&path, "five\n")?;
+        assert_eq!(vec!["four", "five"], follower.poll()?);
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use pullover::team::{self, Role};
+use serde::Deserialize;
+use structopt::StructOpt;
+
+use crate::{input, Opts};
+
+/// Options of `po2 team`
+#[derive(StructOpt)]
+pub enum TeamOpts {
+    /// Print members of the team as CSV <https://pushover.net/api/teams#list>
+    List,
+    /// Add members to the team, who are invited by email <https://pushover.net/api/teams#add_user>
+    Add {
+        /// email of the member
+        #[structopt(long, required_unless = "csv", conflicts_with = "csv")]
+        email: Option<String>,
+        /// name of the member
+        #[structopt(long)]
+        name: Option<String>,
+        /// role of the member e.g. user, admin
+        #[structopt(long, default_value = "user")]
+        role: Role,
+        /// CSV with an `email` column and optional `name` and `role` columns, "-" reads from stdin
+        #[structopt(long)]
+        csv: Option<PathBuf>,
+    },
+    /// Remove members from the team <https://pushover.net/api/teams#remove_user>
+    Remove {
+        /// email of the member
+        #[structopt(long, required_unless = "csv", conflicts_with = "csv")]
+        email: Option<String>,
+        /// CSV with an `email` column, "-" reads from stdin
+        #[structopt(long)]
+        csv: Option<PathBuf>,
+    },
+}
+
+/// Row of input CSV, other columns of e.g. HR export are ignored
+#[derive(Debug, Deserialize)]
+struct Row {
+    email: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    role: Option<String>,
+}
+
+impl Row {
+    fn role(&self) -> anyhow::Result<Role> {
+        match self.role {
+            Some(ref r) => {
+                Role::from_str(&r.to_lowercase()).with_context(|| format!("invalid role: {}", r))
+            }
+            None => Ok(Role::User),
+        }
+    }
+}
+
+fn read_rows(csv: &Option<PathBuf>) -> anyhow::Result<Vec<csv::Result<Row>>> {
+    let reader = input::open(csv.as_deref())?;
+    Ok(csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader)
+        .deserialize()
+        .collect())
+}
+
+/// Runs `po2 team`
+pub async fn run(opts: &Opts, team: &TeamOpts) -> anyhow::Result<()> {
+    let token = opts.token()?;
+    let rows = match team {
+        TeamOpts::List => {
+            let team = team::team(token).await?;
+            let mut writer = csv::Writer::from_writer(io::stdout());
+            for member in team.users {
+                writer.serialize(member)?;
+            }
+            writer.flush()?;
+            return Ok(());
+        }
+        TeamOpts::Add {
+            email: Some(email),
+            name,
+            role,
+            ..
+        } => vec![Ok(Row {
+            email: email.clone(),
+            name: name.clone(),
+            role: Some(role.to_string()),
+        })],
+        TeamOpts::Remove {
+            email: Some(email), ..
+        } => vec![Ok(Row {
+            email: email.clone(),
+            name: None,
+            role: None,
+        })],
+        TeamOpts::Add { csv, .. } | TeamOpts::Remove { csv, .. } => read_rows(csv)?,
+    };
+
+    let (total, mut failures) = (rows.len(), 0);
+    for (i, row) in rows.into_iter().enumerate() {
+        let result = match (team, row) {
+            (_, Err(e)) => Err(e.into()),
+            (TeamOpts::Add { .. }, Ok(r)) => match r.role() {
+                Ok(role) => team::add_user(token, &r.email, r.name.as_deref(), role)
+                    .await
+                    .map_err(Into::into),
+                Err(e) => Err(e),
+            },
+            (_, Ok(r)) => team::remove_user(token, &r.email).await.map_err(Into::into),
+        };
+        if let Err(e) = result {
+            failures += 1;
+            eprintln!("row {}: {:#}", i + 1, e);
+        }
+    }
+
+    if failures > 0 {
+        bail!("{} of {} rows failed", failures, total);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use pullover::team::Role;
+
+    use crate::team::read_rows;
+
+    #[test]
+    fn test_read_rows() {
+        let path = std::env::temp_dir().join(format!("po2-team-{}.csv", std::process::id()));
+        let mut file = std::fs::File::create(&path).unwrap();
+        write!(
+            file,
+            "employee_id,email,name,role\n1,alice@example.com,Alice,Admin\n2,bob@example.com,,\n3,carol@example.com,Carol,owner\n"
+        )
+        .unwrap();
+
+        let rows: Vec<_> = read_rows(&Some(path.clone()))
+            .unwrap()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(3, rows.len());
+        assert_eq!(Role::Admin, rows[0].role().unwrap());
+        assert_eq!("bob@example.com", rows[1].email);
+        assert_eq!(None, rows[1].name);
+        assert_eq!(Role::User, rows[1].role().unwrap());
+        assert!(rows[2].role().is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use pullover::Notification;
+
+use crate::Opts;
+
+/// Message, title, URL and URL title of [`Opts`] rendered as templates
+pub struct Rendered {
+    source: String,
+    message: String,
+    title: String,
+    url: Option<String>,
+    url_title: Option<String>,
+}
+
+impl Rendered {
+    /// Renders options with defaults for message and title
+    pub fn new<F>(opts: &Opts, message: &str, title: &str, lookup: F) -> Self
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let source = render(opts.message.as_deref().unwrap_or(message), &lookup);
+        let message = opts.convert(source.clone());
+        let title = render(opts.title.as_deref().unwrap_or(title), &lookup);
+        let url = opts.url.as_ref().map(|u| render(u, &lookup));
+        let url_title = opts.url_title.as_ref().map(|t| render(t, &lookup));
+        Self {
+            source,
+            message,
+            title,
+            url,
+            url_title,
+        }
+    }
+
+    /// Cuts message and title down to fit the limits
+    pub fn truncate(mut self, opts: &Opts) -> Self {
+        self.message = opts.fit(self.source.clone(), true).unwrap_or_default();
+        self.title = crate::input::fit_title(self.title);
+        self
+    }
+
+    /// Creates a [`Notification`] with other options applied
+    pub fn notification<'a>(&'a self, opts: &'a Opts) -> anyhow::Result<Notification<'a>> {
+        let mut notification = opts.notification(&self.message)?;
+        notification.request.title = Some(self.title.as_str().into());
+        if let Some(ref u) = self.url {
+            notification.request.url = Some(u.into());
+            if let Some(ref t) = self.url_title {
+                notification.request.url_title = Some(t.into());
+            }
+        }
+        Ok(notification)
+    }
+}
+
+/// Renders `{name}` placeholders in template with values looked up by name
+///
+/// Unknown placeholders are rendered as empty string, `{{` and `}}` are rendered as literal braces.
+pub fn render<F>(template: &str, lookup: &F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut rendered = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                rendered.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                rendered.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    name.push(c);
+                }
+                if closed {
+                    rendered.push_str(&lookup(name.trim()).unwrap_or_default());
+                } else {
+                    // not a placeholder after all
+                    rendered.push('{');
+                    rendered.push_str(&name);
+                }
+            }
+            c => rendered.push(c),
+        }
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::template::render;
+
+    #[test]
+    fn test_render() {
+        let lookup = |name: &str| match name {
+            "pid" => Some("42".to_string()),
+            "cmdline" => Some("make -j4".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            "make -j4 (42) exited",
+            render("{cmdline} ({ pid }) exited", &lookup)
+        );
+        assert_eq!("unknown: ", render("unknown: {nope}", &lookup));
+        assert_eq!("{pid} 42", render("{{pid}} {pid}", &lookup));
+        assert_eq!("open {pid", render("open {pid", &lookup));
+    }
+}
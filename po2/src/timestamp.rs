@@ -0,0 +1,76 @@
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context};
+use chrono::DateTime;
+
+/// Date and time of message, parsed from RFC 3339, Unix timestamp prefixed with `@`, or relative to now like `-15m`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timestamp(pub SystemTime);
+
+impl FromStr for Timestamp {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s, SystemTime::now()).map(Timestamp)
+    }
+}
+
+fn parse(s: &str, now: SystemTime) -> anyhow::Result<SystemTime> {
+    let out_of_range = || anyhow!("timestamp {} is out of range", s);
+    if let Some(d) = s.strip_prefix('-') {
+        let d = humantime::parse_duration(d).with_context(|| format!("invalid duration {}", s))?;
+        return now.checked_sub(d).ok_or_else(out_of_range);
+    }
+    if let Some(d) = s.strip_prefix('+') {
+        let d = humantime::parse_duration(d).with_context(|| format!("invalid duration {}", s))?;
+        return now.checked_add(d).ok_or_else(out_of_range);
+    }
+    // plain Unix timestamp is still accepted for compatibility
+    let unix = s.strip_prefix('@').unwrap_or(s);
+    if !unix.is_empty() && unix.chars().all(|c| c.is_ascii_digit()) {
+        let secs = unix.parse().map_err(|_| out_of_range())?;
+        return UNIX_EPOCH
+            .checked_add(Duration::from_secs(secs))
+            .ok_or_else(out_of_range);
+    }
+    let t = DateTime::parse_from_rfc3339(s).with_context(|| {
+        format!(
+            "invalid timestamp {}, expected RFC 3339, @<unix> or relative like -15m",
+            s
+        )
+    })?;
+    Ok(t.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::timestamp::parse;
+
+    #[test]
+    fn test_parse() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_629_451_200);
+        let secs = |s: &str| {
+            parse(s, now)
+                .unwrap()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        };
+        assert_eq!(1_629_451_200, secs("2021-08-20T09:20:00Z"));
+        assert_eq!(1_629_451_200, secs("2021-08-20T17:20:00+08:00"));
+        assert_eq!(1_629_451_200, secs("2021-08-20T09:20:00.5Z"));
+        assert_eq!(1_629_450_300, secs("-15m"));
+        assert_eq!(1_629_445_800, secs("-1h 30m"));
+        assert_eq!(1_629_451_260, secs("+1m"));
+        assert_eq!(1_629_000_000, secs("@1629000000"));
+        assert_eq!(1_629_000_000, secs("1629000000"));
+
+        assert!(parse("yesterday", now).is_err());
+        assert!(parse("-15", now).is_err());
+        assert!(parse("@", now).is_err());
+        assert!(parse("2021-08-20", now).is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use std::fs;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context};
+use structopt::StructOpt;
+
+use crate::exec::format_duration;
+use crate::template::Rendered;
+use crate::Opts;
+
+/// Clock ticks per second used by `/proc/<pid>/stat`, fixed at 100 by the Linux ABI
+const USER_HZ: f64 = 100.0;
+
+/// Options of `po2 wait-pid`
+#[derive(StructOpt)]
+pub struct WaitPidOpts {
+    /// process ID to watch
+    pid: u32,
+    /// seconds between checks of the process
+    #[structopt(long, default_value = "1")]
+    interval: u64,
+}
+
+/// Process being watched, as read from `/proc`
+struct Process {
+    pid: u32,
+    cmdline: String,
+    /// Start time in clock ticks after boot, tells the process apart from a later one reusing its ID
+    start_ticks: u64,
+}
+
+impl Process {
+    fn read(pid: u32) -> anyhow::Result<Self> {
+        let cmdline = fs::read(format!("/proc/{}/cmdline", pid))
+            .with_context(|| format!("no such process: {}", pid))?;
+        // arguments are separated and terminated by NUL
+        let cmdline = String::from_utf8_lossy(&cmdline)
+            .split('\0')
+            .filter(|a| !a.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let (_, start_ticks) =
+            stat(pid).ok_or_else(|| anyhow!("failed to read start time of {}", pid))?;
+        Ok(Self {
+            pid,
+            cmdline,
+            start_ticks,
+        })
+    }
+
+    fn is_running(&self) -> bool {
+        // a zombie has exited already, it is just not reaped by its parent yet
+        match stat(self.pid) {
+            Some((state, ticks)) => ticks == self.start_ticks && state != 'Z' && state != 'X',
+            None => false,
+        }
+    }
+
+    /// How long the process had been running when we started watching
+    fn age(&self) -> Option<Duration> {
+        let uptime = fs::read_to_string("/proc/uptime").ok()?;
+        let uptime: f64 = uptime.split_whitespace().next()?.parse().ok()?;
+        let age = uptime - self.start_ticks as f64 / USER_HZ;
+        Some(Duration::from_secs_f64(age.max(0.0)))
+    }
+}
+
+/// Reads field 3 `state` and field 22 `starttime` of `/proc/<pid>/stat`
+fn stat(pid: u32) -> Option<(char, u64)> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // command name in the second field may contain spaces and parentheses
+    let (_, rest) = stat.rsplit_once(')')?;
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    let state = fields.first()?.chars().next()?;
+    let start_ticks = fields.get(19)?.parse().ok()?;
+    Some((state, start_ticks))
+}
+
+/// Waits for process to exit and sends a notification
+///
+/// Message, title, URL and URL title are templates with `{pid}`, `{cmdline}`, `{elapsed}` and `{host}`.
+pub async fn run(opts: &Opts, wait: &WaitPidOpts) -> anyhow::Result<()> {
+    let process = Process::read(wait.pid)?;
+    let watched = Instant::now();
+    let age = process.age().unwrap_or_default();
+
+    let interval = Duration::from_secs(wait.interval.max(1));
+    while process.is_running() {
+        tokio::time::sleep(interval).await;
+    }
+    let elapsed = age + watched.elapsed();
+
+    let host = hostname::get()?.to_string_lossy().to_string();
+    let rendered = render(opts, &process, elapsed, &host);
+    opts.send(rendered.notification(opts)?).await?;
+
+    Ok(())
+}
+
+/// Renders notification of exited process, cut down to fit the limits
+fn render(opts: &Opts, process: &Process, elapsed: Duration, host: &str) -> Rendered {
+    let lookup = |name: &str| match name {
+        "pid" => Some(process.pid.to_string()),
+        "cmdline" => Some(process.cmdline.clone()),
+        "elapsed" => Some(format_duration(elapsed)),
+        "host" => Some(host.to_string()),
+        _ => None,
+    };
+    Rendered::new(
+        opts,
+        "{cmdline} exited after {elapsed} on {host}",
+        "Process {pid} exited",
+        lookup,
+    )
+    .truncate(opts)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pullover::{MESSAGE_MAX_LENGTH, TITLE_MAX_LENGTH};
+    use structopt::StructOpt;
+
+    use crate::wait_pid::{render, Process};
+    use crate::Opts;
+
+    #[test]
+    fn test_process() {
+        if !std::path::Path::new("/proc/self/stat").exists() {
+            return;
+        }
+        let process = Process::read(std::process::id()).unwrap();
+        assert!(!process.cmdline.is_empty());
+        assert!(process.is_running());
+        assert!(process.age().is_some());
+    }
+
+    #[test]
+    fn test_render() {
+        let args = [
+            "po2",
+            "-t",
+            "token",
+            "-u",
+            "user",
+            "--title",
+            "{cmdline}",
+            "--message",
+            "{cmdline}{cmdline}{cmdline}{cmdline}",
+        ];
+        let opts = Opts::from_iter(&args);
+        let process = Process {
+            pid: 1,
+            cmdline: "x".repeat(300),
+            start_ticks: 0,
+        };
+        let rendered = render(&opts, &process, Duration::from_secs(1), "host");
+        let notification = rendered.notification(&opts).unwrap();
+        let title = notification.request.title.as_deref().unwrap();
+        assert_eq!(TITLE_MAX_LENGTH, title.chars().count());
+        let fields = notification.fields().unwrap();
+        let message = fields.iter().find(|(n, _)| *n == "message").unwrap();
+        assert_eq!(MESSAGE_MAX_LENGTH, message.1.chars().count());
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context};
+use hmac::{Hmac, Mac, NewMac};
+use pullover::{Priority, Sound};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::Sha256;
+use structopt::StructOpt;
+
+use crate::config::Config;
+use crate::{input, template};
+
+/// Route of `po2 serve` translating JSON webhook at `/hooks/<name>` into notification
+#[derive(Debug, Deserialize)]
+pub struct Route {
+    /// Name of route in path
+    pub name: String,
+    /// Secret of HMAC-SHA256 signature of body, which authenticates senders instead of bearer token
+    pub secret: Option<String>,
+    /// Header carrying hex-encoded signature, optionally prefixed with `sha256=`
+    #[serde(default = "default_signature_header")]
+    pub signature_header: String,
+    /// User or group key receiving notifications, otherwise the one of po2
+    pub user: Option<String>,
+    /// Fields extracted from body with JSONPath e.g. `repo = "$.repository.full_name"`
+    #[serde(default)]
+    pub fields: HashMap<String, String>,
+    /// Template of title
+    pub title: Option<String>,
+    /// Template of message
+    pub message: String,
+    /// Template of supplementary URL
+    pub url: Option<String>,
+    /// Template of URL title
+    pub url_title: Option<String>,
+    /// Template of priority e.g. `-2`, `-1`, `0`, `1`, `2`
+    pub priority: Option<String>,
+    /// Template of sound
+    pub sound: Option<String>,
+    /// Seconds between retries of emergency-priority notification
+    #[serde(default = "default_retry")]
+    pub retry: u32,
+    /// Seconds before emergency-priority notification stops retrying
+    #[serde(default = "default_expire")]
+    pub expire: u32,
+}
+
+fn default_signature_header() -> String {
+    "X-Hub-Signature-256".to_string()
+}
+
+fn default_retry() -> u32 {
+    60
+}
+
+fn default_expire() -> u32 {
+    3600
+}
+
+/// Notification rendered from webhook by [`Route`]
+#[derive(Debug, Default)]
+pub struct HookMessage {
+    /// Rendered title
+    pub title: Option<String>,
+    /// Rendered message
+    pub message: String,
+    /// Rendered URL
+    pub url: Option<String>,
+    /// Rendered URL title
+    pub url_title: Option<String>,
+    /// Rendered priority
+    pub priority: Option<Priority>,
+    /// Rendered sound
+    pub sound: Option<Sound>,
+}
+
+impl Route {
+    /// Verifies signature of body when route has a secret
+    pub fn verify(&self, signature: Option<&str>, body: &[u8]) -> bool {
+        let secret = match self.secret {
+            Some(ref s) => s,
+            None => return true,
+        };
+        let signature = match signature {
+            Some(s) => s.trim(),
+            None => return false,
+        };
+        let signature = signature.strip_prefix("sha256=").unwrap_or(signature);
+        let signature = match hex::decode(signature) {
+            Ok(s) => s,
+            Err(_) => return false,
+        };
+        let mut mac = match Hmac::<Sha256>::new_from_slice(secret.as_bytes()) {
+            Ok(m) => m,
+            Err(_) => return false,
+        };
+        mac.update(body);
+        mac.verify(&signature).is_ok()
+    }
+
+    /// Renders notification from JSON body
+    ///
+    /// Templates refer to fields by name e.g. `{repo}`.
+    pub fn render(&self, body: &Value) -> anyhow::Result<HookMessage> {
+        let mut values = HashMap::new();
+        for (name, path) in &self.fields {
+            let found = jsonpath_lib::select(body, path)
+                .map_err(|e| anyhow!("invalid JSONPath of {}: {:?}", name, e))?;
+            let found: Vec<String> = found
+                .into_iter()
+                .map(|v| match v {
+                    Value::String(s) => s.clone(),
+                    v => v.to_string(),
+                })
+                .collect();
+            values.insert(name.as_str(), found.join(", "));
+        }
+        let lookup = |name: &str| values.get(name).cloned();
+        let render = |t: &Option<String>| {
+            t.as_ref()
+                .map(|t| template::render(t, &lookup))
+                .filter(|r| !r.trim().is_empty())
+        };
+
+        let message = template::render(&self.message, &lookup);
+        if message.trim().is_empty() {
+            bail!("message is empty");
+        }
+        let priority = render(&self.priority)
+            .map(|p| {
+                Priority::from_str(p.trim()).with_context(|| format!("invalid priority: {}", p))
+            })
+            .transpose()?;
+        let sound = render(&self.sound)
+            .map(|s| Sound::from_str(s.trim()).with_context(|| format!("invalid sound: {}", s)))
+            .transpose()?;
+        Ok(HookMessage {
+            title: render(&self.title).map(input::fit_title),
+            message: input::fit_message(message, true)?,
+            url: render(&self.url),
+            url_title: render(&self.url_title),
+            priority,
+            sound,
+        })
+    }
+}
+
+/// Options of `po2 webhook`
+#[derive(StructOpt)]
+pub enum WebhookOpts {
+    /// Render notification from payload by route without sending it
+    Test {
+        /// name of route in configuration
+        route: String,
+        /// JSON payload
+        payload: PathBuf,
+    },
+}
+
+/// Runs `po2 webhook`
+pub fn run(config: &Config, webhook: &WebhookOpts) -> anyhow::Result<()> {
+    match webhook {
+        WebhookOpts::Test { route, payload } => {
+            let route = config
+                .serve
+                .routes
+                .iter()
+                .find(|r| &r.name == route)
+                .ok_or_else(|| anyhow!("no such route: {}", route))?;
+            let payload = fs::read(payload)
+                .with_context(|| format!("failed to read {}", payload.display()))?;
+            let body: Value = serde_json::from_slice(&payload)?;
+            let message = route.render(&body)?;
+            println!("title: {}", message.title.unwrap_or_default());
+            println!("url: {}", message.url.unwrap_or_default());
+            println!("url_title: {}", message.url_title.unwrap_or_default());
+            println!(
+                "priority: {}",
+                message.priority.map(|p| p.to_string()).unwrap_or_default()
+            );
+            println!(
+                "sound: {}",
+                message.sound.map(|s| s.to_string()).unwrap_or_default()
+            );
+            println!("message:\n{}", message.message);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pullover::{Priority, Sound, TITLE_MAX_LENGTH};
+    use serde_json::json;
+
+    use crate::webhook::Route;
+
+    fn route() -> Route {
+        toml::from_str(
+            r#"
+            name = "gitea"
+            secret = "secret"
+            title = "{repo}"
+            message = "{pusher} pushed {count} commit(s): {messages}"
+            url = "{url}"
+            priority = "{priority}"
+            sound = "bike"
+
+            [fields]
+            repo = "$.repository.full_name"
+            pusher = "$.pusher.login"
+            count = "$.total_commits"
+            messages = "$.commits[*].message"
+            url = "$.compare_url"
+            priority = "$.missing"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_render() {
+        let body = json!({
+            "repository": {"full_name": "henry40408/pullover"},
+            "pusher": {"login": "henry40408"},
+            "total_commits": 2,
+            "commits": [{"message": "one"}, {"message": "two"}],
+            "compare_url": "https://example.com/compare"
+        });
+        let message = route().render(&body).unwrap();
+        assert_eq!(Some("henry40408/pullover"), message.title.as_deref());
+        assert_eq!("henry40408 pushed 2 commit(s): one, two", message.message);
+        assert_eq!(Some("https://example.com/compare"), message.url.as_deref());
+        assert_eq!(None, message.priority);
+        assert_eq!(Some(Sound::Bike), message.sound);
+
+        let long = json!({"repository": {"full_name": "x".repeat(300)}, "pusher": {}});
+        let title = route().render(&long).unwrap().title.unwrap();
+        assert_eq!(TITLE_MAX_LENGTH, title.chars().count());
+
+        let mut route = route();
+        route.priority = Some("9".into());
+        assert!(route.render(&body).is_err());
+        route.priority = Some("2".into());
+        assert_eq!(
+            Some(Priority::Emergency),
+            route.render(&body).unwrap().priority
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let route = route();
+        let body = br#"{"zen":"hi"}"#;
+        // echo -n '{"zen":"hi"}' | openssl dgst -sha256 -hmac secret
+        let signature = "a3fb8cb2d37fc18eec1b036a46a9a8ebd8e210d829b0b02a4be4d03b1d259492";
+        assert!(route.verify(Some(signature), body));
+        assert!(route.verify(Some(&format!("sha256={}", signature)), body));
+        assert!(!route.verify(Some(signature), b"{}"));
+        assert!(!route.verify(Some("not hex"), body));
+        assert!(!route.verify(None, body));
+
+        let mut route = route;
+        route.secret = None;
+        assert!(route.verify(None, body));
+    }
+}
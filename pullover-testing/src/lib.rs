@@ -0,0 +1,609 @@
+#![deny(
+    missing_docs,
+    missing_debug_implementations,
+    missing_copy_implementations,
+    trivial_casts,
+    trivial_numeric_casts,
+    unsafe_code,
+    unstable_features,
+    unused_import_braces,
+    unused_qualifications
+)]
+
+//! Fake Pushover server recording every message it receives, for tests and staging environments
+//!
+//! ```no_run
+//! use pullover_testing::{Failure, FakeServer};
+//!
+//! # async fn example() -> Result<(), pullover_testing::FakeServerError> {
+//! let server = FakeServer::start().await?;
+//! server.fail_next(Failure::RateLimited);
+//! // point the code under test to server.url(), then
+//! server.assert_count(1);
+//! server.assert_sent("message", "hello");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, VecDeque};
+use std::convert::Infallible;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use hyper::body::Bytes;
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+/// Monthly limit of messages reported in `X-Limit-App-Limit`
+pub const APP_LIMIT: u64 = 10000;
+
+/// Fake server error
+#[derive(Error, Debug)]
+pub enum FakeServerError {
+    /// Error from [`std::io`]
+    #[error("IO error: {0}")]
+    IO(#[from] std::io::Error),
+    /// Error from [`hyper`] crate
+    #[error("hyper error: {0}")]
+    Hyper(#[from] hyper::Error),
+}
+
+/// Attachment decoded from multipart form
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ReceivedAttachment {
+    /// Filename of part
+    pub filename: Option<String>,
+    /// Content type of part
+    pub content_type: Option<String>,
+    /// Number of bytes
+    pub size: usize,
+    /// Content, omitted when serialized
+    #[serde(skip)]
+    pub content: Vec<u8>,
+}
+
+/// Placeholder of `token` and `user` in messages printed or served over HTTP
+pub const REDACTED: &str = "<redacted>";
+
+/// Message received by [`FakeServer`], whether accepted or not
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ReceivedMessage {
+    /// Request ID responded
+    pub request: String,
+    /// HTTP status responded
+    pub status: u16,
+    /// Form fields except attachment e.g. `token`, `user` and `message`
+    pub fields: BTreeMap<String, String>,
+    /// Attachment if any
+    pub attachment: Option<ReceivedAttachment>,
+}
+
+impl ReceivedMessage {
+    /// Value of form field
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(|v| v.as_str())
+    }
+
+    /// Copy with `token` and `user` redacted, to be printed or served over HTTP
+    pub fn redacted(&self) -> Self {
+        let mut message = self.clone();
+        for name in &["token", "user"] {
+            if let Some(v) = message.fields.get_mut(*name) {
+                *v = REDACTED.to_string();
+            }
+        }
+        message
+    }
+}
+
+/// Failure scripted for the next messages, in the order they are queued
+///
+/// Posted to `/_fake/failures` of the standalone server in JSON e.g. `{"kind":"server_error","status":503}`.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Failure {
+    /// HTTP 400 with errors e.g. `user identifier is invalid`
+    Rejected {
+        /// Errors responded
+        errors: Vec<String>,
+    },
+    /// HTTP 429 as the monthly limit is reached
+    RateLimited,
+    /// HTTP 5xx without JSON body
+    ServerError {
+        /// HTTP status e.g. 500, 503
+        status: u16,
+    },
+    /// Successful response after delay
+    Slow {
+        /// Delay in milliseconds
+        delay_ms: u64,
+    },
+}
+
+#[derive(Debug, Default)]
+struct State {
+    messages: Vec<ReceivedMessage>,
+    failures: VecDeque<Failure>,
+    received: u64,
+    accepted: u64,
+    print: bool,
+}
+
+type Shared = Arc<Mutex<State>>;
+
+/// Fake Pushover server, shut down when dropped
+///
+/// Serves `POST /1/messages.json` like Pushover API, and for the standalone server,
+/// `GET /_fake/messages` to inspect messages with `token` and `user` redacted, `DELETE /_fake/messages` to clear them,
+/// and `POST /_fake/failures` to script failures.
+#[derive(Debug)]
+pub struct FakeServer {
+    addr: SocketAddr,
+    url: String,
+    state: Shared,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl FakeServer {
+    /// Starts [`FakeServer`] on a random port of localhost
+    pub async fn start() -> Result<Self, FakeServerError> {
+        Self::bind(([127, 0, 0, 1], 0).into()).await
+    }
+
+    /// Starts [`FakeServer`] on address
+    pub async fn bind(addr: SocketAddr) -> Result<Self, FakeServerError> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let state = Shared::default();
+
+        let shared = Arc::clone(&state);
+        let make_service = make_service_fn(move |_| {
+            let shared = Arc::clone(&shared);
+            async move { Ok::<_, Infallible>(service_fn(move |req| handle(Arc::clone(&shared), req))) }
+        });
+        let (shutdown, rx) = oneshot::channel::<()>();
+        let server = Server::from_tcp(listener)?
+            .serve(make_service)
+            .with_graceful_shutdown(async {
+                let _ = rx.await;
+            });
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                eprintln!("fake server error: {}", e);
+            }
+        });
+
+        Ok(Self {
+            addr,
+            url: format!("http://{}", addr),
+            state,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// Address listened on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// URL to use as base URL of Pushover API e.g. `http://127.0.0.1:12345`
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Prints each received message to stdout as a JSON line
+    pub fn print_messages(&self, enabled: bool) {
+        self.state.lock().unwrap().print = enabled;
+    }
+
+    /// Queues failure for the next message, after the ones queued earlier
+    pub fn fail_next(&self, failure: Failure) {
+        self.state.lock().unwrap().failures.push_back(failure);
+    }
+
+    /// Messages received so far, oldest first
+    pub fn messages(&self) -> Vec<ReceivedMessage> {
+        self.state.lock().unwrap().messages.clone()
+    }
+
+    /// Message received last
+    pub fn last_message(&self) -> Option<ReceivedMessage> {
+        self.state.lock().unwrap().messages.last().cloned()
+    }
+
+    /// Forgets messages and failures not yet used
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.messages.clear();
+        state.failures.clear();
+    }
+
+    /// Waits until at least `count` messages are received, returning messages received in time
+    pub async fn wait_for(&self, count: usize, timeout: Duration) -> Vec<ReceivedMessage> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let messages = self.messages();
+            if messages.len() >= count || Instant::now() >= deadline {
+                return messages;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Asserts number of messages received
+    pub fn assert_count(&self, count: usize) {
+        let messages = self.messages();
+        assert_eq!(
+            count,
+            messages.len(),
+            "expected {} messages, received {:#?}",
+            count,
+            messages
+        );
+    }
+
+    /// Asserts some message is received with field of value
+    pub fn assert_sent(&self, name: &str, value: &str) {
+        let messages = self.messages();
+        assert!(
+            messages.iter().any(|m| m.field(name) == Some(value)),
+            "expected a message with {}={:?}, received {:#?}",
+            name,
+            value,
+            messages
+        );
+    }
+
+    /// Asserts no message is received with field of value
+    pub fn assert_not_sent(&self, name: &str, value: &str) {
+        let messages = self.messages();
+        assert!(
+            !messages.iter().any(|m| m.field(name) == Some(value)),
+            "expected no message with {}={:?}, received {:#?}",
+            name,
+            value,
+            messages
+        );
+    }
+
+    /// Asserts some message satisfies predicate
+    pub fn assert_any<F: Fn(&ReceivedMessage) -> bool>(&self, predicate: F) {
+        let messages = self.messages();
+        assert!(
+            messages.iter().any(predicate),
+            "expected a message satisfying predicate, received {:#?}",
+            messages
+        );
+    }
+}
+
+impl Drop for FakeServer {
+    fn drop(&mut self) {
+        if let Some(s) = self.shutdown.take() {
+            let _ = s.send(());
+        }
+    }
+}
+
+async fn handle(state: Shared, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::POST, "/1/messages.json") => receive(&state, req).await,
+        (&Method::GET, "/_fake/messages") => {
+            let messages: Vec<ReceivedMessage> = state
+                .lock()
+                .unwrap()
+                .messages
+                .iter()
+                .map(|m| m.redacted())
+                .collect();
+            json_response(StatusCode::OK, json!(messages))
+        }
+        (&Method::DELETE, "/_fake/messages") => {
+            state.lock().unwrap().messages.clear();
+            json_response(StatusCode::OK, json!({"status": 1}))
+        }
+        (&Method::POST, "/_fake/failures") => {
+            let body = hyper::body::to_bytes(req.into_body()).await;
+            match body.map(|b| serde_json::from_slice::<Failure>(&b)) {
+                Ok(Ok(f)) => {
+                    state.lock().unwrap().failures.push_back(f);
+                    json_response(StatusCode::OK, json!({"status": 1}))
+                }
+                Ok(Err(e)) => rejected(StatusCode::BAD_REQUEST, "", &[e.to_string()]),
+                Err(e) => rejected(StatusCode::BAD_REQUEST, "", &[e.to_string()]),
+            }
+        }
+        _ => rejected(StatusCode::NOT_FOUND, "", &["not found".to_string()]),
+    };
+    Ok(response)
+}
+
+fn json_response(code: StatusCode, body: Value) -> Response<Body> {
+    let mut response = Response::new(Body::from(body.to_string()));
+    *response.status_mut() = code;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+    response
+}
+
+fn rejected(code: StatusCode, request: &str, errors: &[String]) -> Response<Body> {
+    json_response(
+        code,
+        json!({"status": 0, "request": request, "errors": errors}),
+    )
+}
+
+/// Errors of required fields, like Pushover API
+fn validate(fields: &BTreeMap<String, String>) -> Vec<String> {
+    let blank = |name: &str| !matches!(fields.get(name), Some(v) if !v.trim().is_empty());
+    let mut errors = Vec::new();
+    if blank("token") {
+        errors.push("application token is invalid".to_string());
+    }
+    if blank("user") {
+        errors.push("user identifier is invalid".to_string());
+    }
+    if blank("message") {
+        errors.push("message cannot be blank".to_string());
+    }
+    errors
+}
+
+async fn receive(state: &Shared, req: Request<Body>) -> Response<Body> {
+    let content_type = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let decoded = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) if content_type.starts_with("multipart/form-data") => {
+            decode_multipart(&content_type, body).await
+        }
+        Ok(body) => Ok((decode_form(&body), None)),
+        Err(e) => Err(e.to_string()),
+    };
+    let (fields, attachment) = match decoded {
+        Ok(d) => d,
+        Err(e) => return rejected(StatusCode::BAD_REQUEST, "", &[e]),
+    };
+
+    let errors = validate(&fields);
+    let (received, failure, remaining) = {
+        let mut state = state.lock().unwrap();
+        state.received += 1;
+        let failure = if errors.is_empty() {
+            state.failures.pop_front()
+        } else {
+            None
+        };
+        let accepted = errors.is_empty()
+            && !matches!(
+                failure,
+                Some(Failure::Rejected { .. })
+                    | Some(Failure::RateLimited)
+                    | Some(Failure::ServerError { .. })
+            );
+        if accepted {
+            state.accepted += 1;
+        }
+        let remaining = match failure {
+            Some(Failure::RateLimited) => 0,
+            _ => APP_LIMIT.saturating_sub(state.accepted),
+        };
+        (state.received, failure, remaining)
+    };
+    let request = format!("00000000-0000-4000-8000-{:012x}", received);
+
+    let mut response = if !errors.is_empty() {
+        rejected(StatusCode::BAD_REQUEST, &request, &errors)
+    } else {
+        match failure {
+            Some(Failure::Rejected { ref errors }) => {
+                rejected(StatusCode::BAD_REQUEST, &request, errors)
+            }
+            Some(Failure::RateLimited) => rejected(
+                StatusCode::TOO_MANY_REQUESTS,
+                &request,
+                &["application is over its monthly message limit".to_string()],
+            ),
+            Some(Failure::ServerError { status }) => {
+                let code =
+                    StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                let mut response = Response::new(Body::from(code.to_string()));
+                *response.status_mut() = code;
+                response
+            }
+            Some(Failure::Slow { .. }) | None => {
+                let mut body = json!({"status": 1, "request": request});
+                if fields.get("priority").map(|p| p.as_str()) == Some("2") {
+                    body["receipt"] = json!(format!("r{:029}", received));
+                }
+                json_response(StatusCode::OK, body)
+            }
+        }
+    };
+    let headers = response.headers_mut();
+    headers.insert("X-Limit-App-Limit", APP_LIMIT.into());
+    headers.insert("X-Limit-App-Remaining", remaining.into());
+    headers.insert("X-Limit-App-Reset", next_reset().into());
+
+    let message = ReceivedMessage {
+        request,
+        status: response.status().as_u16(),
+        fields,
+        attachment,
+    };
+    {
+        let mut state = state.lock().unwrap();
+        if state.print {
+            println!("{}", json!(message.redacted()));
+        }
+        state.messages.push(message);
+    }
+
+    if let Some(Failure::Slow { delay_ms }) = failure {
+        tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+    }
+    response
+}
+
+/// Reset of monthly limit, 30 days later for simplicity
+fn next_reset() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    now + 30 * 24 * 60 * 60
+}
+
+fn decode_form(body: &[u8]) -> BTreeMap<String, String> {
+    url::form_urlencoded::parse(body).into_owned().collect()
+}
+
+type Decoded = (BTreeMap<String, String>, Option<ReceivedAttachment>);
+
+async fn decode_multipart(content_type: &str, body: Bytes) -> Result<Decoded, String> {
+    let boundary = multer::parse_boundary(content_type).map_err(|e| e.to_string())?;
+    let stream = futures_util::stream::once(async move { Ok::<_, Infallible>(body) });
+    let mut multipart = multer::Multipart::new(stream, boundary);
+
+    let mut fields = BTreeMap::new();
+    let mut attachment = None;
+    while let Some(field) = multipart.next_field().await.map_err(|e| e.to_string())? {
+        let name = field.name().unwrap_or_default().to_string();
+        if name == "attachment" {
+            let filename = field.file_name().map(|f| f.to_string());
+            let content_type = field.content_type().map(|m| m.to_string());
+            let content = field.bytes().await.map_err(|e| e.to_string())?.to_vec();
+            attachment = Some(ReceivedAttachment {
+                filename,
+                content_type,
+                size: content.len(),
+                content,
+            });
+        } else {
+            let value = field.text().await.map_err(|e| e.to_string())?;
+            fields.insert(name, value);
+        }
+    }
+    Ok((fields, attachment))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use pullover::{Attachment, Notification, NotificationError, Priority};
+
+    use crate::{Failure, FakeServer, APP_LIMIT, REDACTED};
+
+    #[tokio::test]
+    async fn test_receive() -> Result<(), NotificationError> {
+        let server = FakeServer::start().await.unwrap();
+        let attachment = Attachment::new("a.png", "image/png", &[0x89, 0x50, 0x4E, 0x47]);
+        let mut n = Notification::new("token", "user", "hello");
+        n.request.title = Some("title".into());
+        n.request.priority = Some(Priority::Emergency);
+        n.base_url(server.url());
+        n.attach(&attachment);
+
+        let res = n.send().await?;
+        assert_eq!(1, res.status);
+        assert!(res.receipt.is_some());
+
+        server.assert_count(1);
+        server.assert_sent("message", "hello");
+        server.assert_sent("title", "title");
+        server.assert_not_sent("message", "bye");
+        let message = server.last_message().unwrap();
+        assert_eq!(res.request, message.request);
+        let attachment = message.attachment.unwrap();
+        assert_eq!(Some("a.png"), attachment.filename.as_deref());
+        assert_eq!(Some("image/png"), attachment.content_type.as_deref());
+        assert_eq!(vec![0x89, 0x50, 0x4E, 0x47], attachment.content);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failures() -> Result<(), NotificationError> {
+        let server = FakeServer::start().await.unwrap();
+        server.fail_next(Failure::Rejected {
+            errors: vec!["user identifier is invalid".into()],
+        });
+        server.fail_next(Failure::RateLimited);
+        server.fail_next(Failure::ServerError { status: 503 });
+        server.fail_next(Failure::Slow { delay_ms: 100 });
+
+        let mut n = Notification::new("token", "user", "hello");
+        n.base_url(server.url());
+
+        let res = n.send().await?;
+        assert_eq!(0, res.status);
+        assert_eq!(
+            Some(vec!["user identifier is invalid".to_string()]),
+            res.errors
+        );
+        let res = n.send().await?;
+        assert!(res.is_rate_limited());
+        assert_eq!(Some(APP_LIMIT), res.limits.map(|l| l.limit));
+        assert!(matches!(
+            n.send().await,
+            Err(NotificationError::Deserialize(_))
+        ));
+        let started = Instant::now();
+        assert_eq!(1, n.send().await?.status);
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert_eq!(1, n.send().await?.status);
+
+        let statuses: Vec<u16> = server.messages().iter().map(|m| m.status).collect();
+        assert_eq!(vec![400, 429, 503, 200, 200], statuses);
+
+        let mut n = Notification::new("token", "", "hello");
+        n.base_url(server.url());
+        assert_eq!(
+            Some(vec!["user identifier is invalid".to_string()]),
+            n.send().await?.errors
+        );
+
+        server.clear();
+        server.assert_count(0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_admin() {
+        let server = FakeServer::start().await.unwrap();
+        let client = hyper::Client::new();
+        let uri = |path: &str| format!("{}{}", server.url(), path).parse().unwrap();
+
+        let req = hyper::Request::post(uri("/_fake/failures"))
+            .body(r#"{"kind":"server_error","status":500}"#.into())
+            .unwrap();
+        assert_eq!(200, client.request(req).await.unwrap().status());
+        let req = hyper::Request::post(uri("/1/messages.json"))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body("token=token&user=user&message=hello+world".into())
+            .unwrap();
+        assert_eq!(500, client.request(req).await.unwrap().status());
+
+        let res = client.get(uri("/_fake/messages")).await.unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let messages: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!("hello world", messages[0]["fields"]["message"]);
+        assert_eq!(REDACTED, messages[0]["fields"]["token"]);
+        assert_eq!(REDACTED, messages[0]["fields"]["user"]);
+        assert_eq!(500, messages[0]["status"]);
+        assert_eq!(Some("token"), server.messages()[0].field("token"));
+
+        let messages = server.wait_for(2, Duration::from_millis(50)).await;
+        assert_eq!(1, messages.len());
+    }
+}
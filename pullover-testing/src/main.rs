@@ -0,0 +1,44 @@
+#![deny(
+    missing_docs,
+    missing_debug_implementations,
+    missing_copy_implementations,
+    trivial_casts,
+    trivial_numeric_casts,
+    unsafe_code,
+    unstable_features,
+    unused_import_braces,
+    unused_qualifications
+)]
+
+//! pullover-fake-server runs fake Pushover server for staging environments
+
+use std::net::SocketAddr;
+
+use pullover_testing::{FakeServer, FakeServerError};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(about, author)]
+struct Opts {
+    /// address to listen on
+    #[structopt(long, env = "PULLOVER_FAKE_LISTEN", default_value = "127.0.0.1:8080")]
+    listen: SocketAddr,
+    /// do not print received messages to stdout
+    #[structopt(short, long)]
+    quiet: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), FakeServerError> {
+    let opts: Opts = Opts::from_args();
+
+    let server = FakeServer::bind(opts.listen).await?;
+    server.print_messages(!opts.quiet);
+    eprintln!(
+        "pullover-fake-server: listening on {}, set PULLOVER_API_URL={} for pullover",
+        server.addr(),
+        server.url()
+    );
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
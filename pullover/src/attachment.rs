@@ -22,7 +22,7 @@ pub enum AttachmentError {
 }
 
 /// Attachment
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Attachment {
     /// Required. Filename
     pub(crate) filename: String,
@@ -42,7 +42,32 @@ impl Attachment {
         }
     }
 
+    /// Filename
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// MIME type
+    pub fn mime_type(&self) -> &str {
+        &self.mime_type
+    }
+
+    /// Content
+    pub fn content(&self) -> &[u8] {
+        &self.content
+    }
+
     /// Creates an [`Attachment`] with path
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            fields(
+                attachment_size = tracing::field::Empty,
+                mime_type = tracing::field::Empty
+            ),
+            err
+        )
+    )]
     pub async fn from_path(path: &Path) -> Result<Self, AttachmentError> {
         let mut buffer = Vec::new();
         let mut handle = File::open(path)?;
@@ -51,20 +76,41 @@ impl Attachment {
             .file_name()
             .map_or("filename", |t| t.to_str().map_or("filename", |t| t));
         let mime_type = infer::get(&buffer).ok_or(AttachmentError::Infer)?;
+        #[cfg(feature = "tracing")]
+        crate::trace::record_attachment(buffer.len(), mime_type.mime_type());
         Ok(Self::new(filename, mime_type.mime_type(), &buffer))
     }
 
     /// Creates an [`Attachment`] with URL
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip(url),
+            fields(
+                url = tracing::field::Empty,
+                status = tracing::field::Empty,
+                attachment_size = tracing::field::Empty,
+                mime_type = tracing::field::Empty
+            ),
+            err
+        )
+    )]
     pub async fn from_url(url: &str) -> Result<Self, AttachmentError> {
         let parsed = Url::parse(url)?;
-        let filename = parsed
-            .path_segments()
-            .map_or("filename", |t| t.last().map_or("filename", |t1| t1));
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("url", crate::trace::redact_url(&parsed).as_str());
+        let filename = parsed.path_segments().map_or("filename", |mut t| {
+            t.next_back().map_or("filename", |t1| t1)
+        });
 
         let res = reqwest::get(url).await?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("status", res.status().as_u16());
         let buffer = res.bytes().await?.to_vec();
 
         let mime_type = infer::get(&buffer).ok_or(AttachmentError::Infer)?;
+        #[cfg(feature = "tracing")]
+        crate::trace::record_attachment(buffer.len(), mime_type.mime_type());
 
         Ok(Self {
             filename: filename.to_string(),
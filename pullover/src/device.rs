@@ -0,0 +1,151 @@
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+/// Device names are limited to 25 characters <https://pushover.net/api#identifiers>
+pub const DEVICE_NAME_MAX_LENGTH: usize = 25;
+
+/// Device error
+#[derive(Error, Debug)]
+pub enum DeviceError {
+    /// Name is empty, longer than [`DEVICE_NAME_MAX_LENGTH`] or has characters other than letters, numbers, `_` and `-`
+    #[error("invalid device name: {0}")]
+    InvalidName(String),
+    /// Devices are not registered by user
+    #[error("unknown devices: {}", .0.join(", "))]
+    Unknown(Vec<String>),
+}
+
+/// Set of device names, sent comma-separated <https://pushover.net/api#identifiers>
+///
+/// Names are validated and kept in the order they are inserted, without duplicates.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeviceSet {
+    names: Vec<String>,
+}
+
+/// Checks name of device, up to 25 characters of letters, numbers, `_` and `-`
+pub fn check_device_name(name: &str) -> Result<(), DeviceError> {
+    let valid = !name.is_empty()
+        && name.chars().count() <= DEVICE_NAME_MAX_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(DeviceError::InvalidName(name.to_string()))
+    }
+}
+
+impl DeviceSet {
+    /// Creates an empty [`DeviceSet`]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts device name, returning whether it was not in the set
+    pub fn insert(&mut self, name: &str) -> Result<bool, DeviceError> {
+        check_device_name(name)?;
+        if self.contains(name) {
+            return Ok(false);
+        }
+        self.names.push(name.to_string());
+        Ok(true)
+    }
+
+    /// Whether device name is in the set
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.iter().any(|n| n == name)
+    }
+
+    /// Device names in the order they are inserted
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(|n| n.as_str())
+    }
+
+    /// Number of devices
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Whether no device is inserted, which sends to all devices of user
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Checks all devices are registered by user, e.g. [`crate::user::User::devices`]
+    pub fn check<S: AsRef<str>>(&self, devices: &[S]) -> Result<(), DeviceError> {
+        let unknown: Vec<String> = self
+            .iter()
+            .filter(|n| !devices.iter().any(|d| d.as_ref() == *n))
+            .map(|n| n.to_string())
+            .collect();
+        if unknown.is_empty() {
+            Ok(())
+        } else {
+            Err(DeviceError::Unknown(unknown))
+        }
+    }
+}
+
+/// Parses comma-separated device names, ignoring spaces around them
+impl FromStr for DeviceSet {
+    type Err = DeviceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut devices = Self::new();
+        for name in s.split(',').map(|n| n.trim()).filter(|n| !n.is_empty()) {
+            devices.insert(name)?;
+        }
+        Ok(devices)
+    }
+}
+
+/// Formats device names separated by comma
+impl fmt::Display for DeviceSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.names.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::device::{check_device_name, DeviceError, DeviceSet};
+
+    #[test]
+    fn test_device_set() -> Result<(), DeviceError> {
+        let mut devices = DeviceSet::from_str("iphone, nexus5,iphone,")?;
+        assert_eq!(2, devices.len());
+        assert!(!devices.insert("nexus5")?);
+        assert!(devices.insert("desktop_1")?);
+        assert_eq!("iphone,nexus5,desktop_1", devices.to_string());
+        assert!(DeviceSet::from_str("")?.is_empty());
+
+        assert!(DeviceSet::from_str("iphone,my phone").is_err());
+        assert!(devices.insert(&"a".repeat(26)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_device_name() {
+        assert!(check_device_name("build-01_a").is_ok());
+        assert!(check_device_name(&"a".repeat(25)).is_ok());
+        assert!(check_device_name("").is_err());
+        assert!(check_device_name("phone.local").is_err());
+        assert!(check_device_name("téléphone").is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let devices = DeviceSet::from_str("iphone,desktop").unwrap();
+        assert!(devices.check(&["iphone", "desktop", "nexus5"]).is_ok());
+        match devices.check(&["iphone"]) {
+            Err(DeviceError::Unknown(u)) => assert_eq!(vec!["desktop"], u),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+}
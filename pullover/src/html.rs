@@ -0,0 +1,228 @@
+//! Builder of HTML messages with the tags supported by Pushover <https://pushover.net/api#html>
+
+use thiserror::Error;
+use url::Url;
+
+use crate::MESSAGE_MAX_LENGTH;
+
+/// HTML error
+#[derive(Error, Debug)]
+pub enum HtmlError {
+    /// URL of link is not absolute or has a scheme like `javascript:`
+    #[error("invalid URL: {0}")]
+    InvalidUrl(String),
+    /// Color is neither hex like `#ff0000` nor a name like `red`
+    #[error("invalid color: {0}")]
+    InvalidColor(String),
+    /// HTML exceeds [`MESSAGE_MAX_LENGTH`] characters
+    #[error(
+        "HTML has {0} characters, exceeding the limit of {} characters",
+        MESSAGE_MAX_LENGTH
+    )]
+    TooLong(usize),
+}
+
+/// Escapes text for HTML, including quotes so it is safe in attributes
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Checks URL of link, which should be absolute and not run script
+pub fn check_url(url: &str) -> Result<(), HtmlError> {
+    match Url::parse(url) {
+        Ok(u) if !matches!(u.scheme(), "javascript" | "data" | "vbscript") => Ok(()),
+        _ => Err(HtmlError::InvalidUrl(url.to_string())),
+    }
+}
+
+/// Checks color of font, either hex like `#f00` and `#ff0000` or a name like `red`
+pub fn check_color(color: &str) -> Result<(), HtmlError> {
+    let valid = match color.strip_prefix('#') {
+        Some(hex) => {
+            (hex.len() == 3 || hex.len() == 6) && hex.chars().all(|c| c.is_ascii_hexdigit())
+        }
+        None => !color.is_empty() && color.chars().all(|c| c.is_ascii_alphabetic()),
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(HtmlError::InvalidColor(color.to_string()))
+    }
+}
+
+/// HTML message, built from text escaped and tags supported by Pushover
+///
+/// ```
+/// use pullover::html::Html;
+///
+/// let html = Html::new()
+///     .bold("Build")
+///     .text(" of <main> ")
+///     .color("#ff0000", Html::new().italic("failed"))?
+///     .text(", see ")
+///     .link("https://example.com/builds/1", "logs")?
+///     .build()?;
+/// assert_eq!(
+///     r##"<b>Build</b> of &lt;main&gt; <font color="#ff0000"><i>failed</i></font>, see <a href="https://example.com/builds/1">logs</a>"##,
+///     html
+/// );
+/// # Ok::<(), pullover::html::HtmlError>(())
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Html {
+    buffer: String,
+}
+
+impl From<&str> for Html {
+    fn from(text: &str) -> Self {
+        Self {
+            buffer: escape(text),
+        }
+    }
+}
+
+impl From<String> for Html {
+    fn from(text: String) -> Self {
+        Self::from(text.as_str())
+    }
+}
+
+impl Html {
+    /// Creates an empty [`Html`]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn tag(mut self, open: &str, content: Html, close: &str) -> Self {
+        self.buffer.push_str(open);
+        self.buffer.push_str(&content.buffer);
+        self.buffer.push_str(close);
+        self
+    }
+
+    /// Appends text, escaped
+    pub fn text(self, text: &str) -> Self {
+        self.tag("", Html::from(text), "")
+    }
+
+    /// Appends content in `<b>`
+    pub fn bold<T: Into<Html>>(self, content: T) -> Self {
+        self.tag("<b>", content.into(), "</b>")
+    }
+
+    /// Appends content in `<i>`
+    pub fn italic<T: Into<Html>>(self, content: T) -> Self {
+        self.tag("<i>", content.into(), "</i>")
+    }
+
+    /// Appends content in `<u>`
+    pub fn underline<T: Into<Html>>(self, content: T) -> Self {
+        self.tag("<u>", content.into(), "</u>")
+    }
+
+    /// Appends content in `<font color>`
+    pub fn color<T: Into<Html>>(self, color: &str, content: T) -> Result<Self, HtmlError> {
+        check_color(color)?;
+        let open = format!(r#"<font color="{}">"#, escape(color));
+        Ok(self.tag(&open, content.into(), "</font>"))
+    }
+
+    /// Appends content in `<a href>`
+    pub fn link<T: Into<Html>>(self, url: &str, content: T) -> Result<Self, HtmlError> {
+        check_url(url)?;
+        let open = format!(r#"<a href="{}">"#, escape(url));
+        Ok(self.tag(&open, content.into(), "</a>"))
+    }
+
+    /// Number of characters, counted against [`MESSAGE_MAX_LENGTH`]
+    pub fn len(&self) -> usize {
+        self.buffer.chars().count()
+    }
+
+    /// Whether nothing is appended
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// HTML built so far, regardless of length
+    pub fn as_str(&self) -> &str {
+        &self.buffer
+    }
+
+    /// Builds HTML, failing if it exceeds [`MESSAGE_MAX_LENGTH`] characters
+    pub fn build(self) -> Result<String, HtmlError> {
+        let length = self.len();
+        if length > MESSAGE_MAX_LENGTH {
+            return Err(HtmlError::TooLong(length));
+        }
+        Ok(self.buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html::{check_color, check_url, escape, Html, HtmlError};
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            "a &lt;b&gt; &amp; &quot;c&quot; &#39;d&#39;",
+            escape(r#"a <b> & "c" 'd'"#)
+        );
+    }
+
+    #[test]
+    fn test_html() -> Result<(), HtmlError> {
+        let html = Html::new()
+            .text("1 < 2 & ")
+            .bold(Html::new().underline("<3>"))
+            .link("https://example.com/?a=1&b=\"2\"", "link")?
+            .build()?;
+        assert_eq!(
+            r#"1 &lt; 2 &amp; <b><u>&lt;3&gt;</u></b><a href="https://example.com/?a=1&amp;b=&quot;2&quot;">link</a>"#,
+            html
+        );
+
+        assert!(Html::new().link("javascript:alert(1)", "x").is_err());
+        assert!(Html::new().link("/relative", "x").is_err());
+        assert!(Html::new().color("red\" onclick=\"x", "x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(check_color("#f00").is_ok());
+        assert!(check_color("#FF0000").is_ok());
+        assert!(check_color("red").is_ok());
+        assert!(check_color("#ff00").is_err());
+        assert!(check_color("#gg0000").is_err());
+        assert!(check_color("").is_err());
+
+        assert!(check_url("https://example.com").is_ok());
+        assert!(check_url("pushover://updates").is_ok());
+        assert!(check_url("data:text/html,x").is_err());
+    }
+
+    #[test]
+    fn test_length() {
+        let html = Html::new().bold("a".repeat(1017));
+        assert_eq!(1024, html.len());
+        assert!(html.clone().build().is_ok());
+
+        match html.text("&").build() {
+            Err(HtmlError::TooLong(n)) => assert_eq!(1029, n),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+}
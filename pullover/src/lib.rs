@@ -13,31 +13,63 @@
 //! Pullover is Pushover API wrapper with attachment support in Rust 2018 edition
 
 use std::borrow::Cow;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use reqwest::header::HeaderMap;
 use reqwest::multipart;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
 use thiserror::Error;
 
 mod attachment;
+mod device;
+pub mod html;
+pub mod license;
+#[cfg(feature = "markdown")]
+pub mod markdown;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod notifier;
+pub mod open_client;
+pub mod receipt;
+pub mod subscription;
+pub mod team;
+mod token;
+#[cfg(feature = "tracing")]
+mod trace;
+pub mod user;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 
 pub use attachment::{Attachment, AttachmentError};
+pub use device::{check_device_name, DeviceError, DeviceSet, DEVICE_NAME_MAX_LENGTH};
+pub use token::Token;
+
+/// URL of Pushover API
+pub const API_URL: &str = "https://api.pushover.net";
+
+/// Environment variable overriding [`API_URL`], e.g. URL of a fake server in staging
+pub const API_URL_ENV: &str = "PULLOVER_API_URL";
+
+/// Messages are limited to 1024 UTF-8 characters <https://pushover.net/api#limits>
+pub const MESSAGE_MAX_LENGTH: usize = 1024;
+
+/// Titles are limited to 250 characters <https://pushover.net/api#limits>
+pub const TITLE_MAX_LENGTH: usize = 250;
 
 /// Pushover API request <https://pushover.net/api#messages>
 #[derive(Default, Debug)]
 pub struct Request<'a> {
-    token: Cow<'a, str>,
-    user: Cow<'a, str>,
+    token: Token<'a>,
+    user: Token<'a>,
     message: Cow<'a, str>,
-    /// your user's device name to send the message directly to that device, rather than all of the user's devices (multiple devices may be separated by a comma) <https://pushover.net/api#identifiers>
-    pub device: Option<Cow<'a, str>>,
+    /// your user's device names to send the message directly to those devices, rather than all of the user's devices <https://pushover.net/api#identifiers>
+    pub device: Option<DeviceSet>,
     /// your message's title, otherwise your app's name is used <https://pushover.net/api#messages>
     pub title: Option<Cow<'a, str>>,
-    /// To enable HTML formatting <https://pushover.net/api#html>
-    pub html: Option<HTML>,
-    /// To enable monospace messages <https://pushover.net/api#html>
-    pub monospace: Option<Monospace>,
-    /// Messages are stored on the Pushover servers with a timestamp of when they were initially received through the API <https://pushover.net/api#html>
-    pub timestamp: Option<u64>,
+    /// To enable HTML formatting or monospace messages, which are mutually exclusive <https://pushover.net/api#html>
+    pub format: Option<Format>,
+    /// Messages are stored on the Pushover servers with a timestamp of when they were initially received through the API, sent as Unix timestamp <https://pushover.net/api#timestamp>
+    pub timestamp: Option<SystemTime>,
     /// Messages may be sent with a different priority that affects how the message is presented to the user <https://pushover.net/api#priority>
     pub priority: Option<Priority>,
     /// a supplementary URL to show with your message <https://pushover.net/api#urls>
@@ -46,28 +78,76 @@ pub struct Request<'a> {
     pub url_title: Option<Cow<'a, str>>,
     /// Users can choose from a number of different default sounds to play when receiving notifications <https://pushover.net/api#sounds>
     pub sound: Option<Sound>,
+    /// how often (in seconds) the Pushover servers will send the same emergency-priority notification to the user, required with [`Priority::Emergency`] <https://pushover.net/api#priority>
+    pub retry: Option<u32>,
+    /// how many seconds your emergency-priority notification will continue to be retried for, required with [`Priority::Emergency`] <https://pushover.net/api#priority>
+    pub expire: Option<u32>,
+    /// a comma-separated list of tags of emergency-priority notification, for cancelling with [`receipt::cancel_by_tag`] <https://pushover.net/api#receipt>
+    pub tags: Option<Cow<'a, str>>,
+}
+
+impl<'a> Request<'a> {
+    /// Sets [`Request::timestamp`] from [`SystemTime`], or date and time of `chrono` and `time` crates with features
+    pub fn set_timestamp<T: IntoTimestamp>(&mut self, timestamp: T) {
+        self.timestamp = Some(timestamp.into_timestamp());
+    }
+}
+
+/// Date and time which can be the timestamp of [`Request`]
+///
+/// Implemented for `chrono::DateTime` with feature `chrono` and `time::OffsetDateTime` with feature `time`.
+pub trait IntoTimestamp {
+    /// Converts into [`SystemTime`]
+    fn into_timestamp(self) -> SystemTime;
+}
+
+impl IntoTimestamp for SystemTime {
+    fn into_timestamp(self) -> SystemTime {
+        self
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl<Tz: chrono::TimeZone> IntoTimestamp for chrono::DateTime<Tz> {
+    fn into_timestamp(self) -> SystemTime {
+        self.into()
+    }
 }
 
-/// To enable HTML formatting <https://pushover.net/api#html>
+#[cfg(feature = "time")]
+impl IntoTimestamp for time::OffsetDateTime {
+    fn into_timestamp(self) -> SystemTime {
+        self.into()
+    }
+}
+
+/// Format of message, either HTML or monospace but not both <https://pushover.net/api#html>
 #[derive(Clone, Copy, Debug, PartialEq, strum::ToString, strum::EnumString)]
-pub enum HTML {
+#[strum(serialize_all = "lowercase")]
+pub enum Format {
     /// Plain text
-    #[strum(serialize = "0")]
-    None,
+    Plain,
     /// HTML
-    #[strum(serialize = "1")]
-    Enabled,
+    HTML,
+    /// Monospace
+    Monospace,
 }
 
-/// To enable monospace messages <https://pushover.net/api#html>
-#[derive(Clone, Copy, Debug, PartialEq, strum::ToString, strum::EnumString)]
-pub enum Monospace {
-    /// Normal
-    #[strum(serialize = "0")]
-    None,
-    /// Monospace
-    #[strum(serialize = "1")]
-    Enabled,
+/// Both `html` and `monospace` are enabled <https://pushover.net/api#html>
+#[derive(Error, Clone, Copy, Debug)]
+#[error("html and monospace are mutually exclusive")]
+pub struct FormatError;
+
+impl Format {
+    /// Creates [`Format`] from `html` and `monospace` flags of Pushover API, rejecting both enabled
+    pub fn from_flags(html: bool, monospace: bool) -> Result<Self, FormatError> {
+        match (html, monospace) {
+            (true, true) => Err(FormatError),
+            (true, false) => Ok(Format::HTML),
+            (false, true) => Ok(Format::Monospace),
+            (false, false) => Ok(Format::Plain),
+        }
+    }
 }
 
 /// Messages may be sent with a different priority that affects how the message is presented to the user <https://pushover.net/api#priority>
@@ -154,6 +234,9 @@ pub enum NotificationError {
     /// Wrapped [`crate::AttachmentError`]
     #[error("attachment error: {0}")]
     Attachment(#[from] AttachmentError),
+    /// Timestamp is earlier than Unix epoch
+    #[error("timestamp {0:?} is earlier than Unix epoch")]
+    Timestamp(SystemTime),
 }
 
 /// Request wrapped with attachment
@@ -162,6 +245,7 @@ pub struct Notification<'a> {
     /// Actual request sent to Pushover API
     pub request: Request<'a>,
     attachment: Option<&'a Attachment>,
+    base_url: Option<String>,
 }
 
 #[cfg(test)]
@@ -171,7 +255,16 @@ fn server_url() -> String {
 
 #[cfg(not(test))]
 fn server_url() -> String {
-    "https://api.pushover.net".to_string()
+    std::env::var(API_URL_ENV)
+        .map(|u| u.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| API_URL.to_string())
+}
+
+/// Deserializes `0` and `1` of Pushover API into [`bool`]
+pub(crate) fn deserialize_flag<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<bool, D::Error> {
+    Ok(u8::deserialize(deserializer)? != 0)
 }
 
 impl<'a> Notification<'a> {
@@ -193,22 +286,96 @@ impl<'a> Notification<'a> {
         self.attachment = Some(attachment);
     }
 
+    /// Sets URL of Pushover API e.g. of a fake server, otherwise [`API_URL_ENV`] or [`API_URL`]
+    pub fn base_url(&mut self, url: &str) {
+        self.base_url = Some(url.trim_end_matches('/').to_string());
+    }
+
     /// Send [`Request`] to Pushover API
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip(self),
+            fields(
+                endpoint = "/1/messages.json",
+                device = tracing::field::Empty,
+                priority = tracing::field::Empty,
+                attachment_size = tracing::field::Empty,
+                mime_type = tracing::field::Empty,
+                status = tracing::field::Empty,
+                request = tracing::field::Empty,
+                latency_ms = tracing::field::Empty,
+            ),
+            err
+        )
+    )]
     pub async fn send(&'a self) -> Result<Response, NotificationError> {
-        let form = multipart::Form::new()
-            .text("token", self.request.token.to_string())
-            .text("user", self.request.user.to_string())
-            .text("message", self.request.message.to_string());
-
-        let form = Self::append_part(form, "device", self.request.device.as_ref());
-        let form = Self::append_part(form, "title", self.request.title.as_ref());
-        let form = Self::append_part(form, "html", self.request.html.as_ref());
-        let form = Self::append_part(form, "monospace", self.request.monospace.as_ref());
-        let form = Self::append_part(form, "timestamp", self.request.timestamp.as_ref());
-        let form = Self::append_part(form, "priority", self.request.priority.as_ref());
-        let form = Self::append_part(form, "url", self.request.url.as_ref());
-        let form = Self::append_part(form, "url_title", self.request.url_title.as_ref());
-        let form = Self::append_part(form, "sound", self.request.sound.as_ref());
+        #[cfg(feature = "tracing")]
+        trace::record_request(&self.request, self.attachment);
+
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
+        let result = self.post().await;
+        #[cfg(feature = "metrics")]
+        metrics::record_send(self.request.priority, &result, started);
+        result
+    }
+
+    /// Form fields sent to Pushover API in order, except attachment
+    pub fn fields(&self) -> Result<Vec<(&'static str, String)>, NotificationError> {
+        let request = &self.request;
+        let mut fields = vec![
+            ("token", request.token.expose().to_string()),
+            ("user", request.user.expose().to_string()),
+            ("message", request.message.to_string()),
+        ];
+        let mut push = |name: &'static str, value: Option<String>| {
+            if let Some(v) = value {
+                fields.push((name, v));
+            }
+        };
+
+        let device = request.device.as_ref().filter(|d| !d.is_empty());
+        push("device", device.map(|d| d.to_string()));
+        push("title", request.title.as_ref().map(|t| t.to_string()));
+        match request.format {
+            Some(Format::HTML) => push("html", Some("1".to_string())),
+            Some(Format::Monospace) => push("monospace", Some("1".to_string())),
+            Some(Format::Plain) | None => {}
+        }
+        let timestamp = match request.timestamp {
+            Some(t) => match t.duration_since(UNIX_EPOCH) {
+                Ok(d) => Some(d.as_secs().to_string()),
+                Err(_) => return Err(NotificationError::Timestamp(t)),
+            },
+            None => None,
+        };
+        push("timestamp", timestamp);
+        push("priority", request.priority.map(|p| p.to_string()));
+        push("url", request.url.as_ref().map(|u| u.to_string()));
+        push(
+            "url_title",
+            request.url_title.as_ref().map(|t| t.to_string()),
+        );
+        push("sound", request.sound.map(|s| s.to_string()));
+        push("retry", request.retry.map(|r| r.to_string()));
+        push("expire", request.expire.map(|e| e.to_string()));
+        push("tags", request.tags.as_ref().map(|t| t.to_string()));
+        Ok(fields)
+    }
+
+    /// [`Attachment`] attached if any
+    pub fn attachment(&self) -> Option<&'a Attachment> {
+        self.attachment
+    }
+
+    async fn post(&self) -> Result<Response, NotificationError> {
+        let form = self
+            .fields()?
+            .into_iter()
+            .fold(multipart::Form::new(), |form, (name, value)| {
+                form.text(name, value)
+            });
 
         let form = if let Some(a) = self.attachment {
             let part = multipart::Part::bytes(a.content.clone())
@@ -219,36 +386,36 @@ impl<'a> Notification<'a> {
             form
         };
 
-        let uri = format!("{0}/1/messages.json", server_url());
+        let base_url = self.base_url.clone().unwrap_or_else(server_url);
+        let uri = format!("{0}/1/messages.json", base_url);
         let client = reqwest::Client::new();
-        let body = client
-            .post(&uri)
-            .multipart(form)
-            .send()
-            .await?
-            .text()
-            .await?;
-        match serde_json::from_str(&body) {
-            Ok(r) => Ok(r),
-            Err(e) => Err(NotificationError::Deserialize(e)),
+        #[cfg(feature = "tracing")]
+        let started = std::time::Instant::now();
+        let res = client.post(&uri).multipart(form).send().await?;
+        #[cfg(feature = "tracing")]
+        trace::record_status(res.status(), started);
+        let status = res.status().as_u16();
+        let limits = Limits::from_headers(res.headers());
+        #[cfg(feature = "metrics")]
+        if let Some(ref l) = limits {
+            metrics::record_limits(l);
         }
-    }
-
-    fn append_part<T: ToString>(
-        form: multipart::Form,
-        name: &'static str,
-        value: Option<&T>,
-    ) -> multipart::Form {
-        if let Some(v) = value {
-            form.text(name, v.to_string())
-        } else {
-            form
+        let body = res.text().await?;
+        match serde_json::from_str::<Response>(&body) {
+            Ok(mut r) => {
+                r.http_status = Some(status);
+                r.limits = limits;
+                #[cfg(feature = "tracing")]
+                trace::record_response(&r);
+                Ok(r)
+            }
+            Err(e) => Err(NotificationError::Deserialize(e)),
         }
     }
 }
 
 /// Pushover API response <https://pushover.net/api#response>
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Response {
     /// If POST request to API was valid, we will receive an HTTP 200 (OK) status, with a JSON object containing a status code of `1`.
     pub status: u8,
@@ -256,15 +423,57 @@ pub struct Response {
     pub request: String,
     /// …and an `errors` array detailing which parameters were invalid
     pub errors: Option<Vec<String>>,
+    /// When your application sends an emergency-priority notification, our API will respond with a receipt value <https://pushover.net/api#receipt>
+    pub receipt: Option<String>,
+    /// Monthly message limit of application, from headers of response <https://pushover.net/api#limits>
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<Limits>,
+    /// HTTP status of response, e.g. 429 when the monthly message limit is reached
+    #[serde(skip)]
+    pub http_status: Option<u16>,
+}
+
+impl Response {
+    /// Whether rejected as the monthly message limit of application is reached, by HTTP 429 or limit headers <https://pushover.net/api#limits>
+    pub fn is_rate_limited(&self) -> bool {
+        self.status != 1
+            && (self.http_status == Some(429) || matches!(self.limits, Some(l) if l.remaining == 0))
+    }
+}
+
+/// Monthly message limit of application <https://pushover.net/api#limits>
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Limits {
+    /// `X-Limit-App-Limit`, messages the application may send per month
+    pub limit: u64,
+    /// `X-Limit-App-Remaining`, messages the application may still send this month
+    pub remaining: u64,
+    /// `X-Limit-App-Reset`, Unix timestamp when the count resets
+    pub reset: u64,
+}
+
+impl Limits {
+    /// Reads limits from headers of response, if all present
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let get = |name: &str| headers.get(name)?.to_str().ok()?.parse().ok();
+        Some(Self {
+            limit: get("X-Limit-App-Limit")?,
+            remaining: get("X-Limit-App-Remaining")?,
+            reset: get("X-Limit-App-Reset")?,
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use mockito::mock;
+    use mockito::{mock, Matcher};
     use std::str::FromStr;
+    use std::time::{Duration, UNIX_EPOCH};
 
     use crate::attachment::Attachment;
-    use crate::{server_url, Monospace, Notification, NotificationError, Priority, Sound, HTML};
+    use crate::{
+        server_url, DeviceSet, Format, Limits, Notification, NotificationError, Priority, Sound,
+    };
 
     #[test]
     fn test_new() {
@@ -285,15 +494,77 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_limits() -> Result<(), NotificationError> {
+        let _m = mock("POST", "/1/messages.json")
+            .match_body(Matcher::Regex("over the limit".into()))
+            .with_status(429)
+            .with_header("X-Limit-App-Limit", "10000")
+            .with_header("X-Limit-App-Remaining", "0")
+            .with_header("X-Limit-App-Reset", "1393653600")
+            .with_body(r#"{"status":0,"errors":["application is over its monthly message limit"],"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        let res = Notification::new("token", "user", "over the limit")
+            .send()
+            .await?;
+        let limits = Limits {
+            limit: 10000,
+            remaining: 0,
+            reset: 1393653600,
+        };
+        assert_eq!(Some(limits), res.limits);
+        assert!(res.is_rate_limited());
+
+        let _m = mock("POST", "/1/messages.json")
+            .match_body(Matcher::Regex("without headers".into()))
+            .with_status(429)
+            .with_header("X-Limit-App-Limit", "10000")
+            .with_body(r#"{"status":0,"errors":["application is over its monthly message limit"],"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        let res = Notification::new("token", "user", "without headers")
+            .send()
+            .await?;
+        assert_eq!(None, res.limits);
+        assert_eq!(Some(429), res.http_status);
+        assert!(res.is_rate_limited());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_emergency() -> Result<(), NotificationError> {
+        let _m = mock("POST", "/1/messages.json")
+            .with_status(200)
+            .with_body(r#"{"status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b","receipt":"rLqVuqTRh62UzxtmqiaLzQmVcPgiCy"}"#)
+            .create();
+
+        let mut n = build_notification();
+        n.request.priority = Some(Priority::Emergency);
+        n.request.retry = Some(60);
+        n.request.expire = Some(3600);
+        n.request.tags = Some("tag".into());
+
+        let res = n.send().await?;
+        assert_eq!(1, res.status);
+        assert_eq!(
+            Some("rLqVuqTRh62UzxtmqiaLzQmVcPgiCy"),
+            res.receipt.as_deref()
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_device() -> Result<(), NotificationError> {
         let _m = mock("POST", "/1/messages.json")
+            .match_body(Matcher::Regex(
+                r#"name="device"\r\n\r\niphone,nexus5\r\n"#.into(),
+            ))
             .with_status(200)
             .with_body(r#"{"status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
             .create();
 
         let mut n = build_notification();
-        n.request.device = Some("device".into());
+        n.request.device = Some(DeviceSet::from_str("iphone,nexus5,iphone").unwrap());
 
         let res = n.send().await?;
         assert_eq!(1, res.status);
@@ -311,23 +582,81 @@ mod tests {
     }
 
     #[test]
-    fn test_html() -> Result<(), strum::ParseError> {
-        assert_eq!("0", HTML::None.to_string());
-        assert_eq!(HTML::None, HTML::from_str("0")?);
-        assert_eq!("1", HTML::Enabled.to_string());
-        assert_eq!(HTML::Enabled, HTML::from_str("1")?);
+    fn test_format() -> Result<(), strum::ParseError> {
+        assert_eq!("html", Format::HTML.to_string());
+        assert_eq!(Format::Monospace, Format::from_str("monospace")?);
+        assert_eq!(Format::Plain, Format::from_flags(false, false).unwrap());
+        assert_eq!(Format::HTML, Format::from_flags(true, false).unwrap());
+        assert_eq!(Format::Monospace, Format::from_flags(false, true).unwrap());
+        assert!(Format::from_flags(true, true).is_err());
         Ok(())
     }
 
-    #[test]
-    fn test_monospace() -> Result<(), strum::ParseError> {
-        assert_eq!("0", Monospace::None.to_string());
-        assert_eq!(Monospace::None, Monospace::from_str("0")?);
-        assert_eq!("1", Monospace::Enabled.to_string());
-        assert_eq!(Monospace::Enabled, Monospace::from_str("1")?);
+    #[tokio::test]
+    async fn test_send_monospace() -> Result<(), NotificationError> {
+        let _m = mock("POST", "/1/messages.json")
+            .match_body(Matcher::Regex(r#"name="monospace"\r\n\r\n1"#.into()))
+            .with_status(200)
+            .with_body(r#"{"status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+
+        let mut n = Notification::new("token", "user", "monospace");
+        n.request.format = Some(Format::Monospace);
+        let res = n.send().await?;
+        assert_eq!(1, res.status);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_timestamp() -> Result<(), NotificationError> {
+        let _m = mock("POST", "/1/messages.json")
+            .match_body(Matcher::Regex(
+                r#"name="timestamp"\r\n\r\n1629451200\r\n"#.into(),
+            ))
+            .with_status(200)
+            .with_body(r#"{"status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+
+        let mut n = Notification::new("token", "user", "timestamp");
+        n.request
+            .set_timestamp(UNIX_EPOCH + Duration::from_millis(1_629_451_200_500));
+        let res = n.send().await?;
+        assert_eq!(1, res.status);
+
+        n.request.set_timestamp(UNIX_EPOCH - Duration::from_secs(1));
+        assert!(matches!(
+            n.send().await,
+            Err(NotificationError::Timestamp(_))
+        ));
+
         Ok(())
     }
 
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_timestamp_chrono() {
+        let mut n = build_notification();
+        let t = chrono::DateTime::parse_from_rfc3339("2021-08-20T17:20:00+08:00").unwrap();
+        n.request.set_timestamp(t);
+        assert_eq!(
+            Some(UNIX_EPOCH + Duration::from_secs(1_629_451_200)),
+            n.request.timestamp
+        );
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_timestamp_time() {
+        let mut n = build_notification();
+        n.request
+            .set_timestamp(time::OffsetDateTime::from_unix_timestamp(1_629_451_200).unwrap());
+        assert_eq!(
+            Some(UNIX_EPOCH + Duration::from_secs(1_629_451_200)),
+            n.request.timestamp
+        );
+    }
+
     #[test]
     fn test_priority() -> Result<(), strum::ParseError> {
         assert_eq!("-2", Priority::Lowest.to_string());
@@ -0,0 +1,150 @@
+//! Licensing of users by application owner <https://pushover.net/api/licensing>
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::server_url;
+
+/// License error
+#[derive(Error, Debug)]
+pub enum LicenseError {
+    /// Error from [`reqwest`] crate
+    #[error("reqwest error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+    /// Error from [`serde_json`] crate
+    #[error("deserialization error: {0}")]
+    Deserialize(#[from] serde_json::Error),
+    /// Errors returned by Pushover API e.g. invalid user or no credits left
+    #[error("API error: {}", .0.join(", "))]
+    Api(Vec<String>),
+}
+
+/// Operating system a license is limited to <https://pushover.net/api/licensing#assign>
+#[derive(Clone, Copy, Debug, PartialEq, strum::ToString, strum::EnumString)]
+#[strum(ascii_case_insensitive)]
+pub enum OS {
+    /// Android
+    Android,
+    /// iOS
+    #[strum(serialize = "iOS")]
+    IOS,
+    /// Desktop
+    Desktop,
+}
+
+/// Assignee of a license
+#[derive(Clone, Copy, Debug)]
+pub enum Assignee<'a> {
+    /// User key of an existing user
+    User(&'a str),
+    /// Email of user, who is invited to create an account if there is none
+    Email(&'a str),
+}
+
+#[derive(Debug, Deserialize)]
+struct LicenseResponse {
+    status: u8,
+    errors: Option<Vec<String>>,
+    credits: Option<u32>,
+}
+
+impl LicenseResponse {
+    fn check(self) -> Result<Self, LicenseError> {
+        if self.status == 1 {
+            Ok(self)
+        } else {
+            Err(LicenseError::Api(self.errors.unwrap_or_default()))
+        }
+    }
+}
+
+/// Gets remaining license credits <https://pushover.net/api/licensing#credits>
+pub async fn credits(token: &str) -> Result<u32, LicenseError> {
+    let uri = format!("{}/1/licenses.json", server_url());
+    let client = reqwest::Client::new();
+    let body = client
+        .get(&uri)
+        .query(&[("token", token)])
+        .send()
+        .await?
+        .text()
+        .await?;
+    let res = serde_json::from_str::<LicenseResponse>(&body)?.check()?;
+    Ok(res.credits.unwrap_or_default())
+}
+
+/// Assigns a license credit to user, optionally limited to an operating system <https://pushover.net/api/licensing#assign>
+pub async fn assign(
+    token: &str,
+    assignee: Assignee<'_>,
+    os: Option<OS>,
+) -> Result<(), LicenseError> {
+    let os = os.map(|o| o.to_string());
+    let mut form = vec![("token", token)];
+    match assignee {
+        Assignee::User(u) => form.push(("user", u)),
+        Assignee::Email(e) => form.push(("email", e)),
+    }
+    if let Some(ref o) = os {
+        form.push(("os", o));
+    }
+
+    let uri = format!("{}/1/licenses/assign.json", server_url());
+    let client = reqwest::Client::new();
+    let body = client.post(&uri).form(&form).send().await?.text().await?;
+    serde_json::from_str::<LicenseResponse>(&body)?.check()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use mockito::{mock, Matcher};
+
+    use crate::license::{assign, credits, Assignee, LicenseError, OS};
+
+    #[tokio::test]
+    async fn test_credits() -> Result<(), LicenseError> {
+        let _m = mock("GET", "/1/licenses.json")
+            .match_query(Matcher::UrlEncoded("token".into(), "token".into()))
+            .with_status(200)
+            .with_body(
+                r#"{"credits":5,"status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#,
+            )
+            .create();
+        assert_eq!(5, credits("token").await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_assign() -> Result<(), LicenseError> {
+        let _m = mock("POST", "/1/licenses/assign.json")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("email".into(), "user@example.com".into()),
+                Matcher::UrlEncoded("os".into(), "iOS".into()),
+            ]))
+            .with_status(200)
+            .with_body(r#"{"status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        assign("token", Assignee::Email("user@example.com"), Some(OS::IOS)).await?;
+
+        let _m = mock("POST", "/1/licenses/assign.json")
+            .match_body(Matcher::UrlEncoded("user".into(), "invalid".into()))
+            .with_status(400)
+            .with_body(r#"{"user":"invalid","errors":["user key is invalid"],"status":0,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        match assign("token", Assignee::User("invalid"), None).await {
+            Err(LicenseError::Api(e)) => assert_eq!(vec!["user key is invalid"], e),
+            r => panic!("unexpected {:?}", r),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_os() {
+        assert_eq!(OS::IOS, OS::from_str("ios").unwrap());
+        assert_eq!("iOS", OS::IOS.to_string());
+        assert_eq!("Desktop", OS::Desktop.to_string());
+    }
+}
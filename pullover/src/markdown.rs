@@ -0,0 +1,227 @@
+//! Converter from CommonMark to HTML supported by Pushover <https://pushover.net/api#html>
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+
+use crate::html::{check_url, escape};
+
+/// Color of inline code and code blocks
+pub const CODE_COLOR: &str = "#c7254e";
+
+/// Converts Markdown to HTML with tags supported by Pushover
+///
+/// Bold, italic, links and code are converted into `<b>`, `<i>`, `<a href>` and `<font color>`, `<u>` in inline HTML is
+/// kept for underline. Other constructs fall back to text: headings become bold lines, list items are prefixed with
+/// bullets or numbers, and table cells are separated by `|`.
+pub fn to_html(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    let mut writer = Writer::default();
+    for event in Parser::new_ext(markdown, options) {
+        writer.event(event);
+    }
+    writer.out.trim_end().to_string()
+}
+
+#[derive(Default)]
+struct Writer {
+    out: String,
+    /// Next number of ordered lists, or `None` of unordered ones
+    lists: Vec<Option<u64>>,
+    /// Whether links being written are kept, invalid URLs are dropped
+    links: Vec<bool>,
+    /// Whether nothing is written after marker of list item
+    item_start: bool,
+    table_head: bool,
+    table_cell: usize,
+}
+
+impl Writer {
+    fn push(&mut self, s: &str) {
+        self.out.push_str(s);
+        self.item_start = false;
+    }
+
+    /// Starts a new line unless at the start of one
+    fn line_break(&mut self) {
+        if !self.out.is_empty() && !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+    }
+
+    /// Leaves an empty line between blocks
+    fn block_break(&mut self) {
+        self.line_break();
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph if self.lists.is_empty() => self.block_break(),
+            Tag::Paragraph if !self.item_start => self.line_break(),
+            Tag::Paragraph => {}
+            Tag::Heading(_) => {
+                self.block_break();
+                self.push("<b>");
+            }
+            Tag::BlockQuote | Tag::FootnoteDefinition(_) => self.block_break(),
+            Tag::CodeBlock(_) => {
+                if self.lists.is_empty() {
+                    self.block_break();
+                } else if !self.item_start {
+                    self.line_break();
+                }
+                self.push(&format!(r#"<font color="{}">"#, CODE_COLOR));
+            }
+            Tag::List(start) => {
+                if self.lists.is_empty() {
+                    self.block_break();
+                } else {
+                    self.line_break();
+                }
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.line_break();
+                let depth = self.lists.len().saturating_sub(1);
+                self.push(&"  ".repeat(depth));
+                let marker = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => "• ".to_string(),
+                };
+                self.push(&marker);
+                self.item_start = true;
+            }
+            Tag::Table(_) => self.block_break(),
+            Tag::TableHead => {
+                self.table_head = true;
+                self.table_cell = 0;
+            }
+            Tag::TableRow => {
+                self.line_break();
+                self.table_cell = 0;
+            }
+            Tag::TableCell => {
+                if self.table_cell > 0 {
+                    self.push(" | ");
+                }
+                self.table_cell += 1;
+                if self.table_head {
+                    self.push("<b>");
+                }
+            }
+            Tag::Emphasis => self.push("<i>"),
+            Tag::Strong => self.push("<b>"),
+            Tag::Strikethrough => {}
+            Tag::Link(_, url, _) | Tag::Image(_, url, _) => {
+                let valid = check_url(&url).is_ok();
+                if valid {
+                    self.push(&format!(r#"<a href="{}">"#, escape(&url)));
+                }
+                self.links.push(valid);
+            }
+        }
+    }
+
+    fn end(&mut self, tag: Tag) {
+        match tag {
+            Tag::Heading(_) => self.push("</b>"),
+            Tag::CodeBlock(_) => {
+                // code blocks end with a newline, which belongs outside
+                while self.out.ends_with('\n') {
+                    self.out.pop();
+                }
+                self.push("</font>");
+            }
+            Tag::List(_) => {
+                self.lists.pop();
+            }
+            Tag::TableHead => {
+                self.table_head = false;
+                self.line_break();
+            }
+            Tag::TableCell if self.table_head => self.push("</b>"),
+            Tag::Emphasis => self.push("</i>"),
+            Tag::Strong => self.push("</b>"),
+            Tag::Link(..) | Tag::Image(..) => {
+                let kept = self.links.pop().unwrap_or_default();
+                if kept {
+                    self.push("</a>");
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => self.push(&escape(&text)),
+            Event::Code(code) => {
+                let code = format!(r#"<font color="{}">{}</font>"#, CODE_COLOR, escape(&code));
+                self.push(&code);
+            }
+            Event::Html(html) => {
+                let tag = html.trim().to_ascii_lowercase();
+                if tag == "<u>" || tag == "</u>" {
+                    self.push(&tag);
+                } else {
+                    self.push(&escape(&html));
+                }
+            }
+            Event::FootnoteReference(name) => self.push(&format!("[{}]", escape(&name))),
+            Event::SoftBreak | Event::HardBreak => self.push("\n"),
+            Event::Rule => {
+                self.block_break();
+                self.push("———");
+            }
+            Event::TaskListMarker(done) => self.push(if done { "[x] " } else { "[ ] " }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::markdown::to_html;
+
+    #[test]
+    fn test_inline() {
+        assert_eq!(
+            r##"<b>bold</b> <i>italic</i> <u>underline</u> <a href="https://example.com/?a=1&amp;b=2">link</a> <font color="#c7254e">a &lt; b</font>"##,
+            to_html(
+                "**bold** _italic_ <u>underline</u> [link](https://example.com/?a=1&b=2) `a < b`"
+            )
+        );
+        assert_eq!("x &lt;script&gt; y", to_html("x <script> y"));
+        assert_eq!("click", to_html("[click](javascript:alert(1))"));
+        assert_eq!("deleted", to_html("~~deleted~~"));
+    }
+
+    #[test]
+    fn test_blocks() {
+        let markdown =
+            "# Deploy\n\nDone in *3m*.\n\n- web\n- db\n  1. primary\n  2. replica\n\n```\nok\n```";
+        assert_eq!(
+            "<b>Deploy</b>\n\nDone in <i>3m</i>.\n\n• web\n• db\n  1. primary\n  2. replica\n\n<font color=\"#c7254e\">ok</font>",
+            to_html(markdown)
+        );
+    }
+
+    #[test]
+    fn test_loose_list() {
+        assert_eq!("• one\nmore\n• two", to_html("- one\n\n  more\n\n- two"));
+    }
+
+    #[test]
+    fn test_table() {
+        let markdown = "| host | status |\n| --- | --- |\n| web | up |\n| db | down |";
+        assert_eq!(
+            "<b>host</b> | <b>status</b>\nweb | up\ndb | down",
+            to_html(markdown)
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! Metrics of notifications in Prometheus, recorded whether registered or not
+
+use std::time::Instant;
+
+use lazy_static::lazy_static;
+use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry};
+
+use crate::{Limits, NotificationError, Priority, Response};
+
+lazy_static! {
+    static ref SENDS: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "pullover_sends_total",
+            "Notifications sent, by outcome, priority and error kind"
+        ),
+        &["outcome", "priority", "error"]
+    )
+    .unwrap();
+    static ref DURATION: HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "pullover_send_duration_seconds",
+            "Latency of sending notifications, by outcome"
+        ),
+        &["outcome"]
+    )
+    .unwrap();
+    static ref LIMIT: IntGauge = IntGauge::new(
+        "pullover_app_limit",
+        "Messages the application may send per month, from X-Limit-App-Limit"
+    )
+    .unwrap();
+    static ref REMAINING: IntGauge = IntGauge::new(
+        "pullover_app_remaining",
+        "Messages the application may still send this month, from X-Limit-App-Remaining"
+    )
+    .unwrap();
+    static ref RESET: IntGauge = IntGauge::new(
+        "pullover_app_reset_timestamp_seconds",
+        "Unix timestamp when the monthly limit resets, from X-Limit-App-Reset"
+    )
+    .unwrap();
+}
+
+/// Registers metrics to registry <https://pushover.net/api#limits>
+///
+/// - `pullover_sends_total`, counter labeled by `outcome` (`sent`, `rejected` or `failed`), `priority` and `error`
+/// - `pullover_send_duration_seconds`, histogram labeled by `outcome`
+/// - `pullover_app_limit`, `pullover_app_remaining` and `pullover_app_reset_timestamp_seconds`, gauges of the monthly limit
+pub fn register(registry: &Registry) -> Result<(), prometheus::Error> {
+    registry.register(Box::new(SENDS.clone()))?;
+    registry.register(Box::new(DURATION.clone()))?;
+    registry.register(Box::new(LIMIT.clone()))?;
+    registry.register(Box::new(REMAINING.clone()))?;
+    registry.register(Box::new(RESET.clone()))?;
+    Ok(())
+}
+
+/// Records outcome and latency of a notification
+pub(crate) fn record_send(
+    priority: Option<Priority>,
+    result: &Result<Response, NotificationError>,
+    started: Instant,
+) {
+    let (outcome, error) = match result {
+        Ok(r) if r.status == 1 => ("sent", ""),
+        Ok(_) => ("rejected", "api"),
+        Err(NotificationError::Reqwest(_)) => ("failed", "reqwest"),
+        Err(NotificationError::Deserialize(_)) => ("failed", "deserialize"),
+        Err(NotificationError::Attachment(_)) => ("failed", "attachment"),
+        Err(NotificationError::Timestamp(_)) => ("failed", "timestamp"),
+    };
+    let priority = priority.unwrap_or(Priority::Normal).to_string();
+    SENDS.with_label_values(&[outcome, &priority, error]).inc();
+    DURATION
+        .with_label_values(&[outcome])
+        .observe(started.elapsed().as_secs_f64());
+}
+
+/// Records monthly limit of application
+pub(crate) fn record_limits(limits: &Limits) {
+    LIMIT.set(limits.limit as i64);
+    REMAINING.set(limits.remaining as i64);
+    RESET.set(limits.reset as i64);
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{mock, Matcher};
+    use prometheus::{Encoder, Registry, TextEncoder};
+
+    use crate::metrics::register;
+    use crate::{Notification, NotificationError, Priority};
+
+    #[tokio::test]
+    async fn test_metrics() -> Result<(), NotificationError> {
+        let _m = mock("POST", "/1/messages.json")
+            .match_body(Matcher::Regex("metered".into()))
+            .with_status(200)
+            .with_header("X-Limit-App-Limit", "10000")
+            .with_header("X-Limit-App-Remaining", "7496")
+            .with_header("X-Limit-App-Reset", "1393653600")
+            .with_body(r#"{"status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+
+        let mut n = Notification::new("token", "user", "metered");
+        n.request.priority = Some(Priority::Lowest);
+        n.send().await?;
+
+        let registry = Registry::new();
+        register(&registry).unwrap();
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&registry.gather(), &mut buffer)
+            .unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        assert!(
+            text.contains(r#"pullover_sends_total{error="",outcome="sent",priority="-2"} 1"#),
+            "{}",
+            text
+        );
+        assert!(
+            text.contains(r#"pullover_send_duration_seconds_count{outcome="sent"}"#),
+            "{}",
+            text
+        );
+        assert!(text.contains("pullover_app_limit 10000"), "{}", text);
+        assert!(text.contains("pullover_app_remaining 7496"), "{}", text);
+        assert!(
+            text.contains("pullover_app_reset_timestamp_seconds 1393653600"),
+            "{}",
+            text
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+//! Backends sending notifications, swappable in tests or by configuration
+
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::{Attachment, Notification, NotificationError, Response};
+
+/// Request ID of [`Response`] of backends other than [`Http`]
+pub const LOCAL_REQUEST: &str = "00000000-0000-0000-0000-000000000000";
+
+/// Written by [`DryRun`] in place of token and user key
+const REDACTED: &str = "<redacted>";
+
+/// Backend sending notifications
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// Sends [`Notification`]
+    async fn notify(&self, notification: &Notification<'_>) -> Result<Response, NotificationError>;
+}
+
+fn accepted() -> Response {
+    Response {
+        status: 1,
+        request: LOCAL_REQUEST.to_string(),
+        errors: None,
+        receipt: None,
+        limits: None,
+        http_status: None,
+    }
+}
+
+/// Sends to Pushover API with [`Notification::send`]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Http;
+
+#[async_trait]
+impl Notifier for Http {
+    async fn notify(&self, notification: &Notification<'_>) -> Result<Response, NotificationError> {
+        notification.send().await
+    }
+}
+
+/// Writes form fields which would be sent, one `name=value` per line, stderr by default
+///
+/// Token and user key are redacted as the output may end up in logs.
+pub struct DryRun {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl fmt::Debug for DryRun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DryRun").finish()
+    }
+}
+
+impl Default for DryRun {
+    fn default() -> Self {
+        Self::with_writer(io::stderr())
+    }
+}
+
+impl DryRun {
+    /// Creates [`DryRun`] writing to stderr
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates [`DryRun`] writing to writer e.g. stdout
+    pub fn with_writer<W: Write + Send + 'static>(writer: W) -> Self {
+        Self {
+            writer: Mutex::new(Box::new(writer)),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for DryRun {
+    async fn notify(&self, notification: &Notification<'_>) -> Result<Response, NotificationError> {
+        let mut lines = String::new();
+        for (name, value) in notification.fields()? {
+            let value = match name {
+                "token" | "user" => REDACTED,
+                _ => &value,
+            };
+            lines.push_str(&format!("{}={}\n", name, value));
+        }
+        if let Some(a) = notification.attachment() {
+            lines.push_str(&format!(
+                "attachment={} ({}, {} bytes)\n",
+                a.filename,
+                a.mime_type,
+                a.content.len()
+            ));
+        }
+        // a failed write is not a failed notification
+        if let Ok(mut w) = self.writer.lock() {
+            let _ = w.write_all(lines.as_bytes()).and_then(|_| w.flush());
+        }
+        Ok(accepted())
+    }
+}
+
+/// Notification kept by [`Recording`]
+#[derive(Clone, Debug)]
+pub struct Recorded {
+    /// Form fields, see [`Notification::fields`]
+    pub fields: Vec<(&'static str, String)>,
+    /// Attachment if any
+    pub attachment: Option<Attachment>,
+}
+
+impl Recorded {
+    /// Value of form field
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Keeps notifications in memory
+#[derive(Debug, Default)]
+pub struct Recording {
+    notifications: Mutex<Vec<Recorded>>,
+}
+
+impl Recording {
+    /// Creates an empty [`Recording`]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Notifications kept so far, oldest first
+    pub fn notifications(&self) -> Vec<Recorded> {
+        match self.notifications.lock() {
+            Ok(n) => n.clone(),
+            Err(e) => e.into_inner().clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for Recording {
+    async fn notify(&self, notification: &Notification<'_>) -> Result<Response, NotificationError> {
+        let recorded = Recorded {
+            fields: notification.fields()?,
+            attachment: notification.attachment().cloned(),
+        };
+        match self.notifications.lock() {
+            Ok(mut n) => n.push(recorded),
+            Err(e) => e.into_inner().push(recorded),
+        }
+        Ok(accepted())
+    }
+}
+
+/// Discards notifications
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Null;
+
+#[async_trait]
+impl Notifier for Null {
+    async fn notify(&self, _: &Notification<'_>) -> Result<Response, NotificationError> {
+        Ok(accepted())
+    }
+}
+
+/// Backend chosen by configuration e.g. `backend = "dry-run"`
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, strum::ToString, strum::EnumString)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Backend {
+    /// [`Http`]
+    Http,
+    /// [`DryRun`] writing to stderr
+    DryRun,
+    /// [`Recording`]
+    Recording,
+    /// [`Null`]
+    Null,
+}
+
+impl Backend {
+    /// Creates [`Notifier`] of backend
+    pub fn notifier(self) -> Box<dyn Notifier> {
+        match self {
+            Backend::Http => Box::new(Http),
+            Backend::DryRun => Box::new(DryRun::new()),
+            Backend::Recording => Box::new(Recording::new()),
+            Backend::Null => Box::new(Null),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+    use std::str::FromStr;
+    use std::sync::{Arc, Mutex};
+
+    use crate::notifier::{Backend, DryRun, Notifier, Null, Recording};
+    use crate::{Attachment, Format, Notification, NotificationError};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() -> Result<(), NotificationError> {
+        let buffer = Buffer::default();
+        let notifier = DryRun::with_writer(buffer.clone());
+        let attachment = Attachment::new("a.png", "image/png", &[0x89, 0x50]);
+        let mut n = Notification::new("token", "user", "hello");
+        n.request.title = Some("title".into());
+        n.request.format = Some(Format::HTML);
+        n.attach(&attachment);
+
+        let res = notifier.notify(&n).await?;
+        assert_eq!(1, res.status);
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            "token=<redacted>\nuser=<redacted>\nmessage=hello\ntitle=title\nhtml=1\nattachment=a.png (image/png, 2 bytes)\n",
+            output
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recording() -> Result<(), NotificationError> {
+        let notifier = Recording::new();
+        let attachment = Attachment::new("a.png", "image/png", &[0x89, 0x50]);
+        let mut n = Notification::new("token", "user", "first");
+        n.attach(&attachment);
+        notifier.notify(&n).await?;
+        notifier
+            .notify(&Notification::new("token", "user", "second"))
+            .await?;
+
+        let notifications = notifier.notifications();
+        assert_eq!(2, notifications.len());
+        assert_eq!(Some("first"), notifications[0].field("message"));
+        let a = notifications[0].attachment.as_ref().unwrap();
+        assert_eq!("a.png", a.filename());
+        assert_eq!(Some("second"), notifications[1].field("message"));
+        assert!(notifications[1].attachment.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_backend() -> Result<(), NotificationError> {
+        let n = Notification::new("token", "user", "hello");
+        assert_eq!(1, Null.notify(&n).await?.status);
+
+        let backend = Backend::from_str("null").unwrap();
+        assert_eq!(Backend::Null, backend);
+        assert_eq!(1, backend.notifier().notify(&n).await?.status);
+        assert_eq!("dry-run", Backend::DryRun.to_string());
+        Ok(())
+    }
+}
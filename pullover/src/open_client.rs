@@ -0,0 +1,490 @@
+//! Open Client API to receive messages <https://pushover.net/api/client>
+
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+use reqwest::{RequestBuilder, StatusCode};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use thiserror::Error;
+use url::Url;
+
+use crate::{deserialize_flag, server_url, Priority};
+
+/// Open Client error
+#[derive(Error, Debug)]
+pub enum OpenClientError {
+    /// Error from [`reqwest`] crate
+    #[error("reqwest error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+    /// Error from [`serde_json`] crate
+    #[error("deserialization error: {0}")]
+    Deserialize(#[from] serde_json::Error),
+    /// Error from [`std::io`] when storing [`Credentials`]
+    #[error("IO error: {0}")]
+    IO(#[from] std::io::Error),
+    /// Error from [`url`] crate
+    #[error("URL error: {0}")]
+    Url(#[from] url::ParseError),
+    /// User has two-factor authentication enabled, login again with the code <https://pushover.net/api/client#login>
+    #[error("two-factor authentication code is required")]
+    TwoFactorRequired,
+    /// Email, password or two-factor authentication code is invalid
+    #[error("invalid credentials: {}", .0.join(", "))]
+    InvalidCredentials(Vec<String>),
+    /// Device name is invalid or already taken <https://pushover.net/api/client#register>
+    #[error("invalid device: {}", .0.join(", "))]
+    InvalidDevice(Vec<String>),
+    /// Other errors returned by Pushover API
+    #[error("API error: {}", .0.join(", "))]
+    Api(Vec<String>),
+}
+
+/// Session of user logged in, used to register a device <https://pushover.net/api/client#login>
+#[derive(Clone, Deserialize)]
+pub struct Session {
+    /// User key
+    pub id: String,
+    /// Secret of user
+    pub secret: String,
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Session")
+            .field("id", &self.id)
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Secret and device ID of registered device, required by other Open Client API
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Credentials {
+    /// User key
+    pub user_id: String,
+    /// Secret of user
+    pub secret: String,
+    /// Device ID
+    pub device_id: String,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("user_id", &self.user_id)
+            .field("secret", &"<redacted>")
+            .field("device_id", &self.device_id)
+            .finish()
+    }
+}
+
+/// Message downloaded by device <https://pushover.net/api/client#download>
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Message {
+    /// Message ID, incremental per device
+    pub id: u64,
+    /// Unique message ID across devices
+    pub umid: u64,
+    /// Title, or name of application when empty
+    pub title: Option<String>,
+    /// Message
+    pub message: String,
+    /// Name of application
+    pub app: String,
+    /// Application ID
+    pub aid: u64,
+    /// Icon of application <https://pushover.net/api/client#icon>
+    pub icon: String,
+    /// UNIX timestamp of message
+    pub date: u64,
+    /// Priority
+    #[serde(
+        default = "default_priority",
+        deserialize_with = "deserialize_priority",
+        serialize_with = "serialize_priority"
+    )]
+    pub priority: Priority,
+    /// Name of sound, which may be a custom one
+    pub sound: Option<String>,
+    /// Supplementary URL
+    pub url: Option<String>,
+    /// Title of supplementary URL
+    pub url_title: Option<String>,
+    /// Whether emergency-priority message is acknowledged
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    pub acked: bool,
+    /// Receipt of emergency-priority message to acknowledge
+    pub receipt: Option<String>,
+    /// Whether message is HTML
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    pub html: bool,
+}
+
+fn default_priority() -> Priority {
+    Priority::Normal
+}
+
+fn deserialize_priority<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Priority, D::Error> {
+    let p = i8::deserialize(deserializer)?;
+    Priority::from_str(&p.to_string())
+        .map_err(|_| D::Error::custom(format!("invalid priority: {}", p)))
+}
+
+fn serialize_priority<S: Serializer>(
+    priority: &Priority,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let p: i8 = priority
+        .to_string()
+        .parse()
+        .map_err(serde::ser::Error::custom)?;
+    serializer.serialize_i8(p)
+}
+
+/// Response of Open Client API, `errors` may be either an array or an object
+#[derive(Debug, Deserialize)]
+struct ApiResponse {
+    status: u8,
+    #[serde(default)]
+    errors: Value,
+    id: Option<String>,
+    secret: Option<String>,
+    #[serde(default)]
+    messages: Vec<Message>,
+}
+
+impl ApiResponse {
+    fn errors(&self) -> Vec<String> {
+        match &self.errors {
+            Value::Array(a) => a.iter().map(value_to_string).collect(),
+            Value::Object(o) => o
+                .iter()
+                .map(|(k, v)| match v {
+                    Value::Array(a) => {
+                        let v: Vec<String> = a.iter().map(value_to_string).collect();
+                        format!("{} {}", k, v.join(", "))
+                    }
+                    v => format!("{} {}", k, value_to_string(v)),
+                })
+                .collect(),
+            Value::Null => vec![],
+            v => vec![value_to_string(v)],
+        }
+    }
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+fn uri(segments: &[&str]) -> Result<Url, OpenClientError> {
+    let mut uri = Url::parse(&server_url())?;
+    if let Ok(mut s) = uri.path_segments_mut() {
+        s.push("1").extend(segments);
+    }
+    Ok(uri)
+}
+
+async fn send(req: RequestBuilder) -> Result<(StatusCode, ApiResponse), OpenClientError> {
+    let res = req.send().await?;
+    let status = res.status();
+    let body = res.text().await?;
+    Ok((status, serde_json::from_str(&body)?))
+}
+
+async fn post(
+    segments: &[&str],
+    form: &[(&str, &str)],
+) -> Result<(StatusCode, ApiResponse), OpenClientError> {
+    let client = reqwest::Client::new();
+    send(client.post(uri(segments)?).form(form)).await
+}
+
+/// Logs in with email and password, and two-factor authentication code if enabled <https://pushover.net/api/client#login>
+pub async fn login(
+    email: &str,
+    password: &str,
+    twofa: Option<&str>,
+) -> Result<Session, OpenClientError> {
+    let mut form = vec![("email", email), ("password", password)];
+    if let Some(t) = twofa {
+        form.push(("twofa", t));
+    }
+    let (status, res) = post(&["users", "login.json"], &form).await?;
+    if status == StatusCode::PRECONDITION_FAILED {
+        return Err(OpenClientError::TwoFactorRequired);
+    }
+    let errors = res.errors();
+    match (res.status, res.id, res.secret) {
+        (1, Some(id), Some(secret)) => Ok(Session { id, secret }),
+        _ if status.is_client_error() => Err(OpenClientError::InvalidCredentials(errors)),
+        _ => Err(OpenClientError::Api(errors)),
+    }
+}
+
+impl Session {
+    /// Registers a desktop device with name, up to 25 characters of letters, numbers, `_` and `-` <https://pushover.net/api/client#register>
+    pub async fn register_device(&self, name: &str) -> Result<Credentials, OpenClientError> {
+        let form = [
+            ("secret", self.secret.as_str()),
+            ("name", name),
+            ("os", "O"),
+        ];
+        let (status, res) = post(&["devices.json"], &form).await?;
+        let errors = res.errors();
+        match (res.status, res.id) {
+            (1, Some(device_id)) => Ok(Credentials {
+                user_id: self.id.clone(),
+                secret: self.secret.clone(),
+                device_id,
+            }),
+            _ if status.is_client_error() => Err(OpenClientError::InvalidDevice(errors)),
+            _ => Err(OpenClientError::Api(errors)),
+        }
+    }
+}
+
+impl Credentials {
+    /// Loads [`Credentials`] from JSON file
+    pub fn load(path: &Path) -> Result<Self, OpenClientError> {
+        let content = fs::read(path)?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    /// Saves [`Credentials`] to JSON file readable and writable only by owner on Unix
+    pub fn save(&self, path: &Path) -> Result<(), OpenClientError> {
+        let content = serde_json::to_vec_pretty(self)?;
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(0o600);
+            // mode only applies to new file, so restrict existing one as well
+            if path.exists() {
+                fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+            }
+        }
+        let mut file = options.open(path)?;
+        file.write_all(&content)?;
+        Ok(())
+    }
+
+    /// Downloads messages not yet deleted from device <https://pushover.net/api/client#download>
+    pub async fn messages(&self) -> Result<Vec<Message>, OpenClientError> {
+        let client = reqwest::Client::new();
+        let req = client.get(uri(&["messages.json"])?).query(&[
+            ("secret", self.secret.as_str()),
+            ("device_id", self.device_id.as_str()),
+        ]);
+        let (_, res) = send(req).await?;
+        if res.status != 1 {
+            return Err(OpenClientError::Api(res.errors()));
+        }
+        Ok(res.messages)
+    }
+
+    /// Deletes messages up to and including the highest message ID <https://pushover.net/api/client#delete>
+    pub async fn delete_messages(&self, highest_id: u64) -> Result<(), OpenClientError> {
+        let highest_id = highest_id.to_string();
+        let form = [("secret", self.secret.as_str()), ("message", &highest_id)];
+        let segments = ["devices", &self.device_id, "update_highest_message.json"];
+        let (_, res) = post(&segments, &form).await?;
+        if res.status != 1 {
+            return Err(OpenClientError::Api(res.errors()));
+        }
+        Ok(())
+    }
+
+    /// Acknowledges emergency-priority message by its receipt <https://pushover.net/api/client#p2>
+    pub async fn acknowledge(&self, receipt: &str) -> Result<(), OpenClientError> {
+        let form = [("secret", self.secret.as_str())];
+        let segments = ["receipts", receipt, "acknowledge.json"];
+        let (_, res) = post(&segments, &form).await?;
+        if res.status != 1 {
+            return Err(OpenClientError::Api(res.errors()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{mock, Matcher};
+
+    use crate::open_client::{login, Credentials, OpenClientError, Session};
+    use crate::Priority;
+
+    #[tokio::test]
+    async fn test_login() -> Result<(), OpenClientError> {
+        let _m = mock("POST", "/1/users/login.json")
+            .match_body(Matcher::UrlEncoded("email".into(), "user@example.com".into()))
+            .with_status(200)
+            .with_body(r#"{"status":1,"id":"uQiRzpo4DXghDmr9QzzfQu27cmVRsG","secret":"secret","request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        let session = login("user@example.com", "password", None).await?;
+        assert_eq!("uQiRzpo4DXghDmr9QzzfQu27cmVRsG", session.id);
+        assert_eq!("secret", session.secret);
+        assert!(!format!("{:?}", session).contains("secret\""));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_login_two_factor() {
+        let _m = mock("POST", "/1/users/login.json")
+            .match_body(Matcher::UrlEncoded("email".into(), "twofa@example.com".into()))
+            .with_status(412)
+            .with_body(r#"{"status":0,"errors":["two-factor authentication code is required"],"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        let err = login("twofa@example.com", "password", None).await;
+        assert!(matches!(err, Err(OpenClientError::TwoFactorRequired)));
+    }
+
+    #[tokio::test]
+    async fn test_login_invalid() {
+        let _m = mock("POST", "/1/users/login.json")
+            .match_body(Matcher::UrlEncoded("email".into(), "invalid@example.com".into()))
+            .with_status(400)
+            .with_body(r#"{"status":0,"errors":["invalid email and/or password"],"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        match login("invalid@example.com", "password", None).await {
+            Err(OpenClientError::InvalidCredentials(e)) => {
+                assert_eq!(vec!["invalid email and/or password"], e)
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    fn session() -> Session {
+        Session {
+            id: "uQiRzpo4DXghDmr9QzzfQu27cmVRsG".into(),
+            secret: "secret".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_device() -> Result<(), OpenClientError> {
+        let _m = mock("POST", "/1/devices.json")
+            .match_body(Matcher::UrlEncoded("name".into(), "headless".into()))
+            .with_status(200)
+            .with_body(
+                r#"{"status":1,"id":"device_id","request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#,
+            )
+            .create();
+        let credentials = session().register_device("headless").await?;
+        assert_eq!("device_id", credentials.device_id);
+        assert_eq!("secret", credentials.secret);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_register_device_taken() {
+        let _m = mock("POST", "/1/devices.json")
+            .match_body(Matcher::UrlEncoded("name".into(), "taken".into()))
+            .with_status(400)
+            .with_body(r#"{"status":0,"errors":{"name":["has already been taken"]},"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        match session().register_device("taken").await {
+            Err(OpenClientError::InvalidDevice(e)) => {
+                assert_eq!(vec!["name has already been taken"], e)
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_credentials() -> Result<(), OpenClientError> {
+        let path = std::env::temp_dir().join(format!("pullover-{}.json", std::process::id()));
+        let credentials = Credentials {
+            user_id: "user".into(),
+            secret: "secret".into(),
+            device_id: "device".into(),
+        };
+        credentials.save(&path)?;
+        let loaded = Credentials::load(&path)?;
+        assert_eq!("secret", loaded.secret);
+        assert_eq!("device", loaded.device_id);
+        assert!(!format!("{:?}", loaded).contains("\"secret\""));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path)?.permissions().mode();
+            assert_eq!(0o600, mode & 0o777);
+        }
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    fn credentials() -> Credentials {
+        Credentials {
+            user_id: "uQiRzpo4DXghDmr9QzzfQu27cmVRsG".into(),
+            secret: "secret".into(),
+            device_id: "device_id".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_messages() -> Result<(), OpenClientError> {
+        let _m = mock("GET", "/1/messages.json")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("secret".into(), "secret".into()),
+                Matcher::UrlEncoded("device_id".into(), "device_id".into()),
+            ]))
+            .with_status(200)
+            .with_body(r#"{"messages":[{"id":1,"id_str":"1","umid":11,"umid_str":"11","title":"Backup","message":"done","app":"po2","aid":111,"aid_str":"111","icon":"po2","date":1629451200,"priority":0,"acked":0,"html":1,"url":"https://example.com"},{"id":2,"id_str":"2","umid":12,"umid_str":"12","message":"disk full","app":"po2","aid":111,"aid_str":"111","icon":"po2","date":1629451260,"priority":2,"acked":0,"receipt":"receipt","sound":"siren"}],"user":{"quiet_mode":false,"is_android_licensed":false,"is_ios_licensed":false,"is_desktop_licensed":true},"device":{"name":"headless"},"status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        let messages = credentials().messages().await?;
+        assert_eq!(2, messages.len());
+        assert_eq!(Some("Backup"), messages[0].title.as_deref());
+        assert!(messages[0].html);
+        assert_eq!(Priority::Normal, messages[0].priority);
+        assert_eq!(Priority::Emergency, messages[1].priority);
+        assert_eq!(Some("receipt"), messages[1].receipt.as_deref());
+        assert_eq!(Some("siren"), messages[1].sound.as_deref());
+        assert!(!messages[1].acked);
+
+        let json = serde_json::to_value(&messages[1])?;
+        assert_eq!(2, json["priority"]);
+        assert_eq!(false, json["acked"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_delete_messages() -> Result<(), OpenClientError> {
+        let _m = mock("POST", "/1/devices/device_id/update_highest_message.json")
+            .match_body(Matcher::UrlEncoded("message".into(), "2".into()))
+            .with_status(200)
+            .with_body(r#"{"status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        credentials().delete_messages(2).await
+    }
+
+    #[tokio::test]
+    async fn test_acknowledge() -> Result<(), OpenClientError> {
+        let _m = mock("POST", "/1/receipts/receipt/acknowledge.json")
+            .with_status(200)
+            .with_body(r#"{"status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        credentials().acknowledge("receipt").await?;
+
+        let _m = mock("POST", "/1/receipts/invalid/acknowledge.json")
+            .with_status(400)
+            .with_body(r#"{"status":0,"errors":["receipt not found"],"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        match credentials().acknowledge("invalid").await {
+            Err(OpenClientError::Api(e)) => assert_eq!(vec!["receipt not found"], e),
+            r => panic!("unexpected {:?}", r),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+//! Receipts of emergency-priority notifications <https://pushover.net/api/receipts>
+
+use thiserror::Error;
+use url::Url;
+
+use crate::{server_url, Response};
+
+/// Receipt error
+#[derive(Error, Debug)]
+pub enum ReceiptError {
+    /// Error from [`reqwest`] crate
+    #[error("reqwest error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+    /// Error from [`serde_json`] crate
+    #[error("deserialization error: {0}")]
+    Deserialize(#[from] serde_json::Error),
+    /// Error from [`url`] crate
+    #[error("URL error: {0}")]
+    Url(#[from] url::ParseError),
+    /// Errors returned by Pushover API e.g. receipt not found
+    #[error("API error: {}", .0.join(", "))]
+    Api(Vec<String>),
+}
+
+/// Cancels retries of an emergency-priority notification <https://pushover.net/api/receipts#cancel>
+pub async fn cancel(token: &str, receipt: &str) -> Result<Response, ReceiptError> {
+    post(token, &["receipts", receipt, "cancel.json"]).await
+}
+
+/// Cancels retries of emergency-priority notifications sent with the tag <https://pushover.net/api/receipts#cancel_by_tag>
+pub async fn cancel_by_tag(token: &str, tag: &str) -> Result<Response, ReceiptError> {
+    let tag = format!("{}.json", tag);
+    post(token, &["receipts", "cancel_by_tag", &tag]).await
+}
+
+async fn post(token: &str, segments: &[&str]) -> Result<Response, ReceiptError> {
+    let mut uri = Url::parse(&server_url())?;
+    if let Ok(mut s) = uri.path_segments_mut() {
+        s.push("1").extend(segments);
+    }
+    let client = reqwest::Client::new();
+    let body = client
+        .post(uri)
+        .form(&[("token", token)])
+        .send()
+        .await?
+        .text()
+        .await?;
+    let res: Response = serde_json::from_str(&body)?;
+    if res.status == 1 {
+        Ok(res)
+    } else {
+        Err(ReceiptError::Api(res.errors.unwrap_or_default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::mock;
+
+    use crate::receipt::{cancel, cancel_by_tag, ReceiptError};
+
+    #[tokio::test]
+    async fn test_cancel() -> Result<(), ReceiptError> {
+        let _m = mock("POST", "/1/receipts/receipt/cancel.json")
+            .with_status(200)
+            .with_body(r#"{"status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        let res = cancel("token", "receipt").await?;
+        assert_eq!(1, res.status);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cancel_by_tag() -> Result<(), ReceiptError> {
+        let _m = mock("POST", "/1/receipts/cancel_by_tag/am-1234.json")
+            .with_status(200)
+            .with_body(
+                r#"{"status":1,"canceled":2,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#,
+            )
+            .create();
+        let res = cancel_by_tag("token", "am-1234").await?;
+        assert_eq!(1, res.status);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cancel_rejected() {
+        let _m = mock("POST", "/1/receipts/invalid/cancel.json")
+            .with_status(400)
+            .with_body(r#"{"receipt":"not found","errors":["receipt not found; may be invalid or expired"],"status":0,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        match cancel("token", "invalid").await {
+            Err(ReceiptError::Api(e)) => {
+                assert_eq!(vec!["receipt not found; may be invalid or expired"], e)
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+}
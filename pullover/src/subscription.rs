@@ -0,0 +1,113 @@
+//! Subscriptions of users to application <https://pushover.net/api/subscriptions>
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{server_url, Sound};
+
+/// Subscription error
+#[derive(Error, Debug)]
+pub enum SubscriptionError {
+    /// Error from [`reqwest`] crate
+    #[error("reqwest error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+    /// Error from [`serde_json`] crate
+    #[error("deserialization error: {0}")]
+    Deserialize(#[from] serde_json::Error),
+    /// Errors returned by Pushover API e.g. invalid user key or subscription code
+    #[error("API error: {}", .0.join(", "))]
+    Api(Vec<String>),
+}
+
+/// Response of migration <https://pushover.net/api/subscriptions#migration>
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MigrateResponse {
+    /// `1` if user key is migrated
+    pub status: u8,
+    /// Unique token of request
+    pub request: String,
+    /// Invalid parameters if any
+    pub errors: Option<Vec<String>>,
+    /// Subscribed user key to send notifications to, instead of the original one
+    pub subscribed_user_key: Option<String>,
+}
+
+/// Migrates existing user key into subscribed user key of subscription <https://pushover.net/api/subscriptions#migration>
+pub async fn migrate(
+    token: &str,
+    subscription: &str,
+    user: &str,
+    device: Option<&str>,
+    sound: Option<Sound>,
+) -> Result<MigrateResponse, SubscriptionError> {
+    let sound = sound.map(|s| s.to_string());
+    let mut form = vec![
+        ("token", token),
+        ("subscription", subscription),
+        ("user", user),
+    ];
+    if let Some(d) = device {
+        form.push(("device_name", d));
+    }
+    if let Some(ref s) = sound {
+        form.push(("sound", s));
+    }
+
+    let uri = format!("{}/1/subscriptions/migrate.json", server_url());
+    let client = reqwest::Client::new();
+    let body = client.post(&uri).form(&form).send().await?.text().await?;
+    let res: MigrateResponse = serde_json::from_str(&body)?;
+    if res.status == 1 {
+        Ok(res)
+    } else {
+        Err(SubscriptionError::Api(res.errors.unwrap_or_default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{mock, Matcher};
+
+    use crate::subscription::{migrate, SubscriptionError};
+    use crate::Sound;
+
+    #[tokio::test]
+    async fn test_migrate() -> Result<(), SubscriptionError> {
+        let _m = mock("POST", "/1/subscriptions/migrate.json")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("user".into(), "uQiRzpo4DXghDmr9QzzfQu27cmVRsG".into()),
+                Matcher::UrlEncoded("device_name".into(), "iphone".into()),
+                Matcher::UrlEncoded("sound".into(), "bike".into()),
+            ]))
+            .with_status(200)
+            .with_body(r#"{"subscribed_user_key":"uQiRzpo4DXghDmr9QzzfQu27cmVRsG1","status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        let res = migrate(
+            "token",
+            "subscription",
+            "uQiRzpo4DXghDmr9QzzfQu27cmVRsG",
+            Some("iphone"),
+            Some(Sound::Bike),
+        )
+        .await?;
+        assert_eq!(1, res.status);
+        assert_eq!(
+            Some("uQiRzpo4DXghDmr9QzzfQu27cmVRsG1"),
+            res.subscribed_user_key.as_deref()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_migrate_invalid() {
+        let _m = mock("POST", "/1/subscriptions/migrate.json")
+            .match_body(Matcher::UrlEncoded("user".into(), "invalid".into()))
+            .with_status(400)
+            .with_body(r#"{"user":"invalid","errors":["user key is invalid"],"status":0,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        match migrate("token", "subscription", "invalid", None, None).await {
+            Err(SubscriptionError::Api(e)) => assert_eq!(vec!["user key is invalid"], e),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+}
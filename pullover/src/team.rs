@@ -0,0 +1,198 @@
+//! Management of team members of Pushover for Teams <https://pushover.net/api/teams>
+
+use serde::{Deserialize, Deserializer, Serialize};
+use thiserror::Error;
+
+use crate::server_url;
+
+/// Team error
+#[derive(Error, Debug)]
+pub enum TeamError {
+    /// Error from [`reqwest`] crate
+    #[error("reqwest error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+    /// Error from [`serde_json`] crate
+    #[error("deserialization error: {0}")]
+    Deserialize(#[from] serde_json::Error),
+    /// Errors returned by Pushover API e.g. invalid email or user not in team
+    #[error("API error: {}", .0.join(", "))]
+    Api(Vec<String>),
+}
+
+/// Role of team member
+#[derive(
+    Clone, Copy, Debug, PartialEq, Deserialize, Serialize, strum::ToString, strum::EnumString,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Role {
+    /// Member receiving notifications
+    User,
+    /// Member who can also manage the team
+    Admin,
+}
+
+fn default_role() -> Role {
+    Role::User
+}
+
+/// Deserializes `admin` flag of Pushover API, `1` or `true`, into [`Role`]
+fn deserialize_role<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Role, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Flag {
+        Bool(bool),
+        Number(u8),
+    }
+    let admin = match Flag::deserialize(deserializer)? {
+        Flag::Bool(b) => b,
+        Flag::Number(n) => n != 0,
+    };
+    Ok(if admin { Role::Admin } else { Role::User })
+}
+
+/// Member of team
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Member {
+    /// Email
+    pub email: String,
+    /// Name
+    pub name: Option<String>,
+    /// User key, absent until the member creates an account
+    pub user: Option<String>,
+    /// Role in team, from `admin` flag of Pushover API
+    #[serde(
+        rename(deserialize = "admin"),
+        default = "default_role",
+        deserialize_with = "deserialize_role"
+    )]
+    pub role: Role,
+}
+
+/// Team and its members <https://pushover.net/api/teams#list>
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Team {
+    /// Name of team
+    pub name: String,
+    /// Members of team
+    #[serde(default)]
+    pub users: Vec<Member>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TeamResponse {
+    status: u8,
+    errors: Option<Vec<String>>,
+    #[serde(flatten)]
+    team: Option<Team>,
+}
+
+impl TeamResponse {
+    fn check(self) -> Result<Self, TeamError> {
+        if self.status == 1 {
+            Ok(self)
+        } else {
+            Err(TeamError::Api(self.errors.unwrap_or_default()))
+        }
+    }
+}
+
+async fn post(path: &str, form: &[(&str, &str)]) -> Result<(), TeamError> {
+    let uri = format!("{}{}", server_url(), path);
+    let client = reqwest::Client::new();
+    let body = client.post(&uri).form(form).send().await?.text().await?;
+    serde_json::from_str::<TeamResponse>(&body)?.check()?;
+    Ok(())
+}
+
+/// Lists members of team by its API token <https://pushover.net/api/teams#list>
+pub async fn team(token: &str) -> Result<Team, TeamError> {
+    let uri = format!("{}/1/teams.json", server_url());
+    let client = reqwest::Client::new();
+    let body = client
+        .get(&uri)
+        .query(&[("token", token)])
+        .send()
+        .await?
+        .text()
+        .await?;
+    let res = serde_json::from_str::<TeamResponse>(&body)?.check()?;
+    res.team
+        .ok_or_else(|| TeamError::Api(vec!["team is missing in response".to_string()]))
+}
+
+/// Adds member to team, who is invited by email <https://pushover.net/api/teams#add_user>
+pub async fn add_user(
+    token: &str,
+    email: &str,
+    name: Option<&str>,
+    role: Role,
+) -> Result<(), TeamError> {
+    let mut form = vec![("token", token), ("email", email)];
+    if let Some(n) = name {
+        form.push(("name", n));
+    }
+    if role == Role::Admin {
+        form.push(("admin", "1"));
+    }
+    post("/1/teams/add_user.json", &form).await
+}
+
+/// Removes member from team <https://pushover.net/api/teams#remove_user>
+pub async fn remove_user(token: &str, email: &str) -> Result<(), TeamError> {
+    post(
+        "/1/teams/remove_user.json",
+        &[("token", token), ("email", email)],
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{mock, Matcher};
+
+    use crate::team::{add_user, remove_user, team, Role, TeamError};
+
+    #[tokio::test]
+    async fn test_team() -> Result<(), TeamError> {
+        let _m = mock("GET", "/1/teams.json")
+            .match_query(Matcher::UrlEncoded("token".into(), "token".into()))
+            .with_status(200)
+            .with_body(r#"{"name":"Ops","users":[{"email":"alice@example.com","name":"Alice","user":"uQiRzpo4DXghDmr9QzzfQu27cmVRsG","admin":1},{"email":"bob@example.com","name":null,"user":null,"admin":0},{"email":"carol@example.com","name":"Carol","user":null}],"status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        let team = team("token").await?;
+        assert_eq!("Ops", team.name);
+        assert_eq!(3, team.users.len());
+        assert_eq!(Role::Admin, team.users[0].role);
+        assert_eq!(Role::User, team.users[1].role);
+        assert_eq!(Role::User, team.users[2].role);
+        assert_eq!(None, team.users[1].user);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_add_user() -> Result<(), TeamError> {
+        let _m = mock("POST", "/1/teams/add_user.json")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("email".into(), "alice@example.com".into()),
+                Matcher::UrlEncoded("admin".into(), "1".into()),
+            ]))
+            .with_status(200)
+            .with_body(r#"{"status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        add_user("token", "alice@example.com", Some("Alice"), Role::Admin).await
+    }
+
+    #[tokio::test]
+    async fn test_remove_user() {
+        let _m = mock("POST", "/1/teams/remove_user.json")
+            .match_body(Matcher::UrlEncoded("email".into(), "carol@example.com".into()))
+            .with_status(400)
+            .with_body(r#"{"errors":["user is not a member of this team"],"status":0,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        match remove_user("token", "carol@example.com").await {
+            Err(TeamError::Api(e)) => assert_eq!(vec!["user is not a member of this team"], e),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::borrow::Cow;
+use std::fmt;
+
+/// API token or user key, redacted in [`Debug`] so it never shows up in logs <https://pushover.net/api#identifiers>
+#[derive(Clone, Default, PartialEq)]
+pub struct Token<'a>(Cow<'a, str>);
+
+impl<'a> Token<'a> {
+    /// Wraps token or user key
+    pub fn new<S: Into<Cow<'a, str>>>(token: S) -> Self {
+        Self(token.into())
+    }
+
+    /// Token or user key in plain text, only to be sent to Pushover API
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Token(<redacted>)")
+    }
+}
+
+impl<'a> From<&'a str> for Token<'a> {
+    fn from(token: &'a str) -> Self {
+        Self::new(token)
+    }
+}
+
+impl From<String> for Token<'_> {
+    fn from(token: String) -> Self {
+        Self::new(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Notification, Token};
+
+    #[test]
+    fn test_debug() {
+        let token = Token::from("azGDORePK8gMaC0QOYAMyEEuzJnyUi");
+        assert_eq!("Token(<redacted>)", format!("{:?}", token));
+        assert_eq!("azGDORePK8gMaC0QOYAMyEEuzJnyUi", token.expose());
+
+        let n = Notification::new("secret-token", "secret-user", "hello");
+        let debug = format!("{:?}", n);
+        assert!(!debug.contains("secret-token"), "{}", debug);
+        assert!(!debug.contains("secret-user"), "{}", debug);
+        assert!(debug.contains("hello"), "{}", debug);
+    }
+}
@@ -0,0 +1,91 @@
+//! Validation of user or group key <https://pushover.net/api#verification>
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{deserialize_flag, server_url};
+
+/// User error
+#[derive(Error, Debug)]
+pub enum UserError {
+    /// Error from [`reqwest`] crate
+    #[error("reqwest error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+    /// Error from [`serde_json`] crate
+    #[error("deserialization error: {0}")]
+    Deserialize(#[from] serde_json::Error),
+    /// Errors returned by Pushover API e.g. invalid user key or device
+    #[error("API error: {}", .0.join(", "))]
+    Api(Vec<String>),
+}
+
+/// User or group with active devices <https://pushover.net/api#verification>
+#[derive(Debug, Deserialize)]
+pub struct User {
+    /// Whether key is of a group
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    pub group: bool,
+    /// Names of active devices
+    #[serde(default)]
+    pub devices: Vec<String>,
+    /// Operating systems of licenses e.g. `Android`, `iOS`
+    #[serde(default)]
+    pub licenses: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserResponse {
+    status: u8,
+    errors: Option<Vec<String>>,
+    #[serde(flatten)]
+    user: User,
+}
+
+/// Validates user or group key, and device name if any <https://pushover.net/api#verification>
+pub async fn validate(token: &str, user: &str, device: Option<&str>) -> Result<User, UserError> {
+    let mut form = vec![("token", token), ("user", user)];
+    if let Some(d) = device {
+        form.push(("device", d));
+    }
+
+    let uri = format!("{}/1/users/validate.json", server_url());
+    let client = reqwest::Client::new();
+    let body = client.post(&uri).form(&form).send().await?.text().await?;
+    let res: UserResponse = serde_json::from_str(&body)?;
+    if res.status == 1 {
+        Ok(res.user)
+    } else {
+        Err(UserError::Api(res.errors.unwrap_or_default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{mock, Matcher};
+
+    use crate::user::{validate, UserError};
+
+    #[tokio::test]
+    async fn test_validate() -> Result<(), UserError> {
+        let _m = mock("POST", "/1/users/validate.json")
+            .match_body(Matcher::UrlEncoded("user".into(), "valid".into()))
+            .with_status(200)
+            .with_body(r#"{"status":1,"group":0,"devices":["iphone","nexus5"],"licenses":["Android","iOS"],"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        let user = validate("token", "valid", None).await?;
+        assert!(!user.group);
+        assert_eq!(vec!["iphone", "nexus5"], user.devices);
+        assert_eq!(vec!["Android", "iOS"], user.licenses);
+
+        let _m = mock("POST", "/1/users/validate.json")
+            .match_body(Matcher::UrlEncoded("user".into(), "invalid".into()))
+            .with_status(400)
+            .with_body(r#"{"user":"invalid","errors":["user key is invalid"],"status":0,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+        match validate("token", "invalid", Some("iphone")).await {
+            Err(UserError::Api(e)) => assert_eq!(vec!["user key is invalid"], e),
+            r => panic!("unexpected {:?}", r),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,261 @@
+//! Realtime notification of Open Client through WebSocket <https://pushover.net/api/client#websocket>
+
+use std::time::Duration;
+
+use futures_util::stream::{self, Stream};
+use futures_util::{SinkExt, StreamExt};
+use thiserror::Error;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message as Frame;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+use crate::open_client::{Credentials, Message, OpenClientError};
+
+/// URL of WebSocket server of Pushover
+pub const WEBSOCKET_URL: &str = "wss://client.pushover.net/push";
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// WebSocket error
+#[derive(Error, Debug)]
+pub enum WebSocketError {
+    /// Error from [`tokio_tungstenite`] crate
+    #[error("WebSocket error: {0}")]
+    WebSocket(#[from] tokio_tungstenite::tungstenite::Error),
+    /// Error when downloading messages
+    #[error("open client error: {0}")]
+    OpenClient(#[from] OpenClientError),
+    /// No frame, not even keep-alive, is received in time
+    #[error("no frame received in {0:?}")]
+    Timeout(Duration),
+    /// Connection is closed by server
+    #[error("connection closed")]
+    Closed,
+    /// Frame is not one of `#`, `!`, `R`, `E` or `A`
+    #[error("unexpected frame: {0}")]
+    UnexpectedFrame(String),
+    /// `E`, permanent error and device should log in again, stream ends afterwards
+    #[error("session error, log in again")]
+    Session,
+    /// `A`, device logged in from another session, stream ends afterwards
+    #[error("device logged in elsewhere")]
+    LoggedInElsewhere,
+}
+
+/// Event from WebSocket server
+#[derive(Debug)]
+pub enum Event {
+    /// Connected and logged in with device secret
+    Connected,
+    /// `#`, keep-alive
+    KeepAlive,
+    /// `!`, new messages downloaded automatically, which should be deleted once processed
+    Messages(Vec<Message>),
+    /// `R`, server requests to reconnect, which is done automatically
+    Reconnect,
+}
+
+/// Listener of WebSocket server, reconnecting with exponential backoff
+#[derive(Debug)]
+pub struct Listener {
+    credentials: Credentials,
+    url: String,
+    timeout: Duration,
+    min_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Listener {
+    /// Creates a [`Listener`] of device
+    pub fn new(credentials: Credentials) -> Self {
+        Self {
+            credentials,
+            url: WEBSOCKET_URL.to_string(),
+            timeout: Duration::from_secs(90),
+            min_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(300),
+        }
+    }
+
+    /// Sets URL of WebSocket server, [`WEBSOCKET_URL`] by default
+    pub fn url(&mut self, url: &str) {
+        self.url = url.to_string();
+    }
+
+    /// Sets time without any frame before reconnecting, 90 seconds by default
+    pub fn timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Sets delay before reconnecting, doubled after each failure, 1 to 300 seconds by default
+    pub fn backoff(&mut self, min: Duration, max: Duration) {
+        self.min_backoff = min;
+        self.max_backoff = max;
+    }
+
+    /// Listens to WebSocket server
+    ///
+    /// Errors are yielded as they occur and the stream goes on by reconnecting,
+    /// except [`WebSocketError::Session`] and [`WebSocketError::LoggedInElsewhere`] which end the stream.
+    pub fn listen(self) -> impl Stream<Item = Result<Event, WebSocketError>> {
+        let state = State {
+            backoff: self.min_backoff,
+            listener: self,
+            socket: None,
+            delay: None,
+            done: false,
+        };
+        stream::unfold(state, |mut state| async move {
+            if state.done {
+                return None;
+            }
+            let item = state.next().await;
+            Some((item, state))
+        })
+    }
+}
+
+struct State {
+    listener: Listener,
+    socket: Option<Socket>,
+    backoff: Duration,
+    delay: Option<Duration>,
+    done: bool,
+}
+
+impl State {
+    async fn connect(&self) -> Result<Socket, WebSocketError> {
+        let (mut socket, _) = connect_async(self.listener.url.as_str()).await?;
+        let c = &self.listener.credentials;
+        let login = format!("login:{}:{}\n", c.device_id, c.secret);
+        socket.send(Frame::Text(login)).await?;
+        Ok(socket)
+    }
+
+    fn disconnect(&mut self) {
+        self.socket = None;
+        self.delay = Some(self.backoff);
+        self.backoff = std::cmp::min(self.backoff * 2, self.listener.max_backoff);
+    }
+
+    async fn next(&mut self) -> Result<Event, WebSocketError> {
+        loop {
+            let socket = match self.socket.as_mut() {
+                Some(s) => s,
+                None => {
+                    if let Some(delay) = self.delay.take() {
+                        tokio::time::sleep(delay).await;
+                    }
+                    return match self.connect().await {
+                        Ok(s) => {
+                            self.socket = Some(s);
+                            self.backoff = self.listener.min_backoff;
+                            Ok(Event::Connected)
+                        }
+                        Err(e) => {
+                            self.disconnect();
+                            Err(e)
+                        }
+                    };
+                }
+            };
+
+            let timeout = self.listener.timeout;
+            let frame = match tokio::time::timeout(timeout, socket.next()).await {
+                Err(_) => Err(WebSocketError::Timeout(timeout)),
+                Ok(None) | Ok(Some(Ok(Frame::Close(_)))) => Err(WebSocketError::Closed),
+                Ok(Some(Err(e))) => Err(e.into()),
+                Ok(Some(Ok(f))) => Ok(f),
+            };
+            let data = match frame {
+                Ok(Frame::Binary(d)) => d,
+                Ok(Frame::Text(t)) => t.into_bytes(),
+                // ping and pong are answered by tungstenite
+                Ok(_) => continue,
+                Err(e) => {
+                    self.disconnect();
+                    return Err(e);
+                }
+            };
+
+            return match data.as_slice() {
+                b"#" => Ok(Event::KeepAlive),
+                b"!" => Ok(Event::Messages(self.listener.credentials.messages().await?)),
+                b"R" => {
+                    self.socket = None;
+                    Ok(Event::Reconnect)
+                }
+                b"E" => {
+                    self.done = true;
+                    Err(WebSocketError::Session)
+                }
+                b"A" => {
+                    self.done = true;
+                    Err(WebSocketError::LoggedInElsewhere)
+                }
+                d => Err(WebSocketError::UnexpectedFrame(
+                    String::from_utf8_lossy(d).to_string(),
+                )),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_util::{SinkExt, StreamExt};
+    use mockito::{mock, Matcher};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::Message as Frame;
+
+    use crate::open_client::Credentials;
+    use crate::websocket::{Event, Listener, WebSocketError};
+
+    async fn serve(listener: &TcpListener, frames: &[&[u8]]) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+        let login = socket.next().await.unwrap().unwrap();
+        assert_eq!(Frame::Text("login:websocket:secret\n".into()), login);
+        for f in frames {
+            socket.send(Frame::Binary(f.to_vec())).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_listen() {
+        let _m = mock("GET", "/1/messages.json")
+            .match_query(Matcher::UrlEncoded("device_id".into(), "websocket".into()))
+            .with_status(200)
+            .with_body(r#"{"messages":[{"id":1,"umid":11,"message":"hello","app":"po2","aid":111,"icon":"po2","date":1629451200,"priority":0}],"status":1,"request":"647d2300-702c-4b38-8b2f-d56326ae460b"}"#)
+            .create();
+
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            serve(&server, &[b"#", b"!", b"R"]).await;
+            serve(&server, &[b"A"]).await;
+        });
+
+        let mut listener = Listener::new(Credentials {
+            user_id: "user".into(),
+            secret: "secret".into(),
+            device_id: "websocket".into(),
+        });
+        listener.url(&format!("ws://{}", addr));
+        listener.backoff(Duration::from_millis(10), Duration::from_millis(100));
+        let events: Vec<_> = listener.listen().collect().await;
+
+        assert_eq!(6, events.len());
+        assert!(matches!(events[0], Ok(Event::Connected)));
+        assert!(matches!(events[1], Ok(Event::KeepAlive)));
+        match &events[2] {
+            Ok(Event::Messages(m)) => assert_eq!("hello", m[0].message),
+            e => panic!("unexpected {:?}", e),
+        }
+        assert!(matches!(events[3], Ok(Event::Reconnect)));
+        assert!(matches!(events[4], Ok(Event::Connected)));
+        assert!(matches!(events[5], Err(WebSocketError::LoggedInElsewhere)));
+    }
+}